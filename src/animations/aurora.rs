@@ -0,0 +1,94 @@
+use cichlid::HSV;
+use rs_ws281x::RawColor;
+
+use super::{
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 3] = [
+    ParameterSchema {
+        name: "speed",
+        description: "How fast the curtains of light move",
+        kind: ParameterKind::Float,
+        min: 0.01,
+        max: 2.0,
+        default: 0.2,
+    },
+    ParameterSchema {
+        name: "scale",
+        description: "Size of the curtains of light, in LEDs",
+        kind: ParameterKind::Integer,
+        min: 2.0,
+        max: 100.0,
+        default: 20.0,
+    },
+    ParameterSchema {
+        name: "brightness",
+        description: "Brightness of the brightest curtains, in percent",
+        kind: ParameterKind::Integer,
+        min: 1.0,
+        max: 100.0,
+        default: 60.0,
+    },
+];
+
+/// Greens to purples, in the hues of `HSV`
+const HUES: (f32, f32) = (80.0, 200.0);
+
+/// Curtains of green and purple light waving along the strip
+pub struct Aurora {
+    /// Seconds since the animation started
+    pub time: f32,
+    pub parameters: Parameters,
+}
+impl Aurora {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            time: 0.0,
+            parameters: Parameters::new(&SCHEMA, config, "aurora"),
+        }
+    }
+}
+/// Pseudo-random value between 0 and 1 for a point of the grid
+fn hash(x: i32, y: i32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(374_761_393) ^ (y as u32).wrapping_mul(668_265_263);
+    h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+    (h ^ (h >> 16)) as f32 / u32::MAX as f32
+}
+/// Smooth value noise between 0 and 1
+fn noise(x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+    let (x0, y0) = (x0 as i32, y0 as i32);
+    let top = hash(x0, y0) + (hash(x0 + 1, y0) - hash(x0, y0)) * tx;
+    let bottom = hash(x0, y0 + 1) + (hash(x0 + 1, y0 + 1) - hash(x0, y0 + 1)) * tx;
+    top + (bottom - top) * ty
+}
+impl Animation for Aurora {
+    fn update(&mut self, delta: f32) {
+        self.time += delta;
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let speed = self.parameters.get("speed");
+        let scale = self.parameters.get("scale");
+        let brightness = self.parameters.get("brightness") / 100.0;
+        let time = self.time * speed;
+        for (position, led) in leds.iter_mut().enumerate() {
+            let x = position as f32 / scale;
+            // The hue and the light come from unrelated parts of the noise
+            let hue = HUES.0 + (HUES.1 - HUES.0) * noise(x * 0.5, time * 0.5 + 1000.0);
+            let light = noise(x, time).powi(2) * brightness * 255.0;
+            let rgb = HSV::new(hue as u8, 255, light as u8).to_rgb_rainbow();
+            *led = [rgb.b, rgb.g, rgb.r, 0];
+        }
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
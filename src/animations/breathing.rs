@@ -0,0 +1,76 @@
+use std::f32::consts::PI;
+
+use rs_ws281x::RawColor;
+
+use super::{
+    canvas::mix,
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::functions::hex_to_rgb;
+use crate::structs::{ColorMode, Config};
+
+pub const SCHEMA: [ParameterSchema; 2] = [
+    ParameterSchema {
+        name: "period",
+        description: "Seconds of a breath",
+        kind: ParameterKind::Float,
+        min: 1.0,
+        max: 60.0,
+        default: 6.0,
+    },
+    ParameterSchema {
+        name: "min_brightness",
+        description: "Brightness between two breaths, in percent",
+        kind: ParameterKind::Integer,
+        min: 0.0,
+        max: 100.0,
+        default: 10.0,
+    },
+];
+
+/// The colors of the color mode, slowly brightening and dimming
+pub struct Breathing {
+    pub colors: Vec<RawColor>,
+    /// Seconds since the animation started
+    pub time: f32,
+    pub parameters: Parameters,
+}
+impl Breathing {
+    pub fn new(config: &Config) -> Self {
+        let rgb = hex_to_rgb(&config.leds.color_mode);
+        Self {
+            colors: vec![[rgb[2], rgb[1], rgb[0], 0]; config.leds.num_leds],
+            time: 0.0,
+            parameters: Parameters::new(&SCHEMA, config, "breathing"),
+        }
+    }
+}
+impl Animation for Breathing {
+    fn start(&mut self) {
+        self.time = 0.0;
+    }
+    fn update(&mut self, delta: f32) {
+        self.time += delta;
+    }
+    fn set_color_mode(&mut self, color_mode: &ColorMode) {
+        for (position, color) in self.colors.iter_mut().enumerate() {
+            *color = color_mode.get_color(position, 0);
+        }
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let period = self.parameters.get("period");
+        let min_brightness = self.parameters.get("min_brightness") / 100.0;
+        let phase = self.time / period * 2.0 * PI;
+        let brightness = min_brightness + (1.0 - min_brightness) * (0.5 - 0.5 * phase.cos());
+        for (led, color) in leds.iter_mut().zip(self.colors.iter()) {
+            *led = mix([0, 0, 0, 0], *color, brightness);
+        }
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
@@ -0,0 +1,45 @@
+use rs_ws281x::RawColor;
+
+use crate::leds::mapping::Key;
+
+/// Frame summing the light of several sources, so that they add up where
+/// they overlap instead of overwriting each other
+pub struct Canvas(Vec<[f32; 4]>);
+impl Canvas {
+    pub fn new(num_leds: usize) -> Canvas {
+        Canvas(vec![[0.0; 4]; num_leds])
+    }
+    /// Adds `color` scaled by `intensity` at `position`, if it is on the strip
+    pub fn add(&mut self, position: usize, color: RawColor, intensity: f32) {
+        if let Some(sum) = self.0.get_mut(position) {
+            for (channel, color) in sum.iter_mut().zip(color.iter()) {
+                *channel += *color as f32 * intensity;
+            }
+        }
+    }
+    /// Lights the whole span of a key, its edges lit by the part of their LED
+    /// they cover
+    pub fn add_key(&mut self, key: &Key, color: RawColor, intensity: f32) {
+        for position in key.span() {
+            self.add(
+                position,
+                color,
+                intensity * key.brightness * key.coverage(position),
+            );
+        }
+    }
+    pub fn draw(&self, leds: &mut [RawColor]) {
+        for (led, sum) in leds.iter_mut().zip(self.0.iter()) {
+            *led = sum.map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+        }
+    }
+}
+
+/// Mixes `from` into `to`, `amount` going from 0 (only `from`) to 1
+pub fn mix(from: RawColor, to: RawColor, amount: f32) -> RawColor {
+    let mut color = [0; 4];
+    for i in 0..4 {
+        color[i] = (from[i] as f32 + (to[i] as f32 - from[i] as f32) * amount).round() as u8;
+    }
+    color
+}
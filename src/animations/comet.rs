@@ -0,0 +1,145 @@
+use rand::prelude::*;
+use rs_ws281x::RawColor;
+
+use super::{
+    canvas::Canvas,
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::leds::mapping::Key;
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 3] = [
+    ParameterSchema {
+        name: "speed",
+        description: "LEDs travelled by a comet each second, faster for wide intervals",
+        kind: ParameterKind::Float,
+        min: 1.0,
+        max: 600.0,
+        default: 90.0,
+    },
+    ParameterSchema {
+        name: "tail_length",
+        description: "Length of the tail of a comet, in LEDs",
+        kind: ParameterKind::Integer,
+        min: 1.0,
+        max: 60.0,
+        default: 15.0,
+    },
+    ParameterSchema {
+        name: "decay",
+        description: "Brightness a comet keeps after a second",
+        kind: ParameterKind::Float,
+        min: 0.001,
+        max: 1.0,
+        default: 0.55,
+    },
+];
+
+/// Below this, a comet wouldn't light a LED anymore
+const MIN_AMPLITUDE: f32 = 1.0 / 255.0;
+
+#[derive(Debug)]
+pub struct Comet {
+    pub position: f32,
+    /// LEDs travelled each second, negative towards the start of the strip
+    pub velocity: f32,
+    pub amplitude: f32,
+    pub color: RawColor,
+}
+impl Comet {
+    /// Brightness at `position`, the tail fading quadratically behind the head
+    fn intensity(&self, position: f32, tail_length: f32) -> f32 {
+        let behind = (self.position - position) * self.velocity.signum();
+        if !(-1.0..=tail_length).contains(&behind) {
+            return 0.0;
+        }
+        let weight = if behind < 0.0 {
+            1.0 + behind
+        } else {
+            (1.0 - behind / tail_length).powi(2)
+        };
+        weight * self.amplitude
+    }
+}
+#[derive(Debug)]
+pub struct Comets {
+    pub num_leds: usize,
+    pub comets: Vec<Comet>,
+    /// Position of the previous note, to follow the direction of the melody
+    pub last_position: Option<usize>,
+    pub parameters: Parameters,
+}
+impl Comets {
+    pub fn new(config: &Config) -> Comets {
+        Comets {
+            num_leds: config.leds.num_leds,
+            comets: Vec::new(),
+            last_position: None,
+            parameters: Parameters::new(&SCHEMA, config, "comet"),
+        }
+    }
+}
+impl Animation for Comets {
+    fn note_on(&mut self, key: &Key, color: RawColor, velocity: u8) {
+        let position = key.center();
+        let interval = match self.last_position {
+            Some(last_position) => position as f32 - last_position as f32,
+            None => 0.0,
+        };
+        // Repeated notes have no direction, they go either way
+        let direction = if interval == 0.0 {
+            if rand::thread_rng().gen_bool(0.5) {
+                1.0
+            } else {
+                -1.0
+            }
+        } else {
+            interval.signum()
+        };
+        let speed = self.parameters.get("speed") * (1.0 + (interval.abs() / 24.0).min(2.0));
+        self.comets.push(Comet {
+            position: position as f32,
+            velocity: direction * speed,
+            amplitude: 0.3 + 0.7 * velocity.min(127) as f32 / 127.0,
+            color,
+        });
+        self.last_position = Some(position);
+    }
+    fn update(&mut self, delta: f32) {
+        let decay = self.parameters.get("decay").powf(delta);
+        let tail_length = self.parameters.get("tail_length");
+        for comet in self.comets.iter_mut() {
+            comet.position += comet.velocity * delta;
+            comet.amplitude *= decay;
+        }
+        // A comet is over once it faded out or its tail left the strip
+        let num_leds = self.num_leds as f32;
+        self.comets.retain(|comet| {
+            let tail = comet.position - comet.velocity.signum() * tail_length;
+            comet.amplitude >= MIN_AMPLITUDE && tail > -1.0 && tail < num_leds
+        });
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let tail_length = self.parameters.get("tail_length");
+        let mut canvas = Canvas::new(leds.len());
+        for comet in self.comets.iter() {
+            let start = (comet.position - tail_length - 1.0).max(0.0) as usize;
+            let end = (comet.position + tail_length + 1.0).max(0.0) as usize;
+            for position in start..=end.min(leds.len()) {
+                canvas.add(
+                    position,
+                    comet.color,
+                    comet.intensity(position as f32, tail_length),
+                );
+            }
+        }
+        canvas.draw(leds);
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
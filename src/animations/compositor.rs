@@ -0,0 +1,85 @@
+use rocket::serde::{Deserialize, Serialize};
+use rs_ws281x::RawColor;
+
+use super::{transition::Transition, Animation};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum BlendMode {
+    Normal,
+    Add,
+    Multiply,
+    Screen,
+    Max,
+}
+impl BlendMode {
+    fn blend_channel(&self, bottom: u8, top: u8) -> u8 {
+        let (bottom, top) = (bottom as u16, top as u16);
+        (match self {
+            BlendMode::Normal => top,
+            BlendMode::Add => (bottom + top).min(255),
+            BlendMode::Multiply => bottom * top / 255,
+            BlendMode::Screen => 255 - (255 - bottom) * (255 - top) / 255,
+            BlendMode::Max => bottom.max(top),
+        }) as u8
+    }
+    /// Blends `top` over `bottom`, then mixes the result with `bottom`
+    /// according to `opacity`
+    pub fn blend(&self, bottom: RawColor, top: RawColor, opacity: f32) -> RawColor {
+        let mut color = [0; 4];
+        for i in 0..4 {
+            let blended = self.blend_channel(bottom[i], top[i]) as f32;
+            color[i] = (bottom[i] as f32 + (blended - bottom[i] as f32) * opacity).round() as u8;
+        }
+        color
+    }
+}
+
+/// An animation drawn on its own frame, to be composited with the others
+pub struct Layer {
+    pub id: usize,
+    pub animation: String,
+    pub animator: Box<dyn Animation>,
+    pub opacity: f32,
+    pub blend: BlendMode,
+    /// Kept between frames, animations only redraw what changed
+    pub frame: Vec<RawColor>,
+    pub transition: Option<Transition>,
+}
+impl Layer {
+    /// Frame of the layer, mixed with the outgoing one during a transition
+    pub fn output(&self) -> Vec<RawColor> {
+        let mut output = self.frame.clone();
+        if let Some(transition) = &self.transition {
+            transition.apply(&mut output);
+        }
+        output
+    }
+    pub fn info(&self) -> LayerInfo {
+        LayerInfo {
+            id: self.id,
+            animation: self.animation.to_string(),
+            opacity: self.opacity,
+            blend: self.blend,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct LayerInfo {
+    pub id: usize,
+    pub animation: String,
+    pub opacity: f32,
+    pub blend: BlendMode,
+}
+
+/// Composites the layers, bottom first, into `leds`
+pub fn composite(layers: &[Layer], leds: &mut [RawColor]) {
+    leds.fill([0, 0, 0, 0]);
+    for layer in layers.iter() {
+        for (led, color) in leds.iter_mut().zip(layer.output().iter()) {
+            *led = layer.blend.blend(*led, *color, layer.opacity);
+        }
+    }
+}
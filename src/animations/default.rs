@@ -0,0 +1,41 @@
+use rs_ws281x::RawColor;
+
+use super::{canvas::Canvas, parameters::Parameters, Animation};
+use crate::leds::mapping::Key;
+use crate::structs::Config;
+
+/// Lights the held keys
+pub struct DefaultAnimator {
+    pub held: Vec<(Key, RawColor)>,
+    pub parameters: Parameters,
+}
+impl DefaultAnimator {
+    pub fn new(_config: &Config) -> Self {
+        Self {
+            held: Vec::new(),
+            parameters: Parameters::default(),
+        }
+    }
+}
+impl Animation for DefaultAnimator {
+    fn note_on(&mut self, key: &Key, color: RawColor, _velocity: u8) {
+        self.held.retain(|(held, _)| held.note != key.note);
+        self.held.push((key.clone(), color));
+    }
+    fn note_off(&mut self, key: &Key, _color: RawColor) {
+        self.held.retain(|(held, _)| held.note != key.note);
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let mut canvas = Canvas::new(leds.len());
+        for (key, color) in self.held.iter() {
+            canvas.add_key(key, *color, 1.0);
+        }
+        canvas.draw(leds);
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
@@ -0,0 +1,64 @@
+use cichlid::HSV;
+use rs_ws281x::RawColor;
+
+use super::{
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 2] = [
+    ParameterSchema {
+        name: "period",
+        description: "Seconds for the rainbow to drift across the whole strip",
+        kind: ParameterKind::Float,
+        min: 5.0,
+        max: 600.0,
+        default: 60.0,
+    },
+    ParameterSchema {
+        name: "brightness",
+        description: "Brightness of the rainbow, in percent",
+        kind: ParameterKind::Integer,
+        min: 1.0,
+        max: 100.0,
+        default: 40.0,
+    },
+];
+
+/// A dim rainbow slowly drifting along the strip
+pub struct Drift {
+    /// Seconds since the animation started
+    pub time: f32,
+    pub parameters: Parameters,
+}
+impl Drift {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            time: 0.0,
+            parameters: Parameters::new(&SCHEMA, config, "drift"),
+        }
+    }
+}
+impl Animation for Drift {
+    fn update(&mut self, delta: f32) {
+        self.time += delta;
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let period = self.parameters.get("period");
+        let value = (self.parameters.get("brightness") / 100.0 * 255.0) as u8;
+        let shift = self.time / period;
+        let num_leds = leds.len() as f32;
+        for (position, led) in leds.iter_mut().enumerate() {
+            let hue = ((position as f32 / num_leds + shift).fract() * 256.0) as u8;
+            let rgb = HSV::new(hue, 255, value).to_rgb_rainbow();
+            *led = [rgb.b, rgb.g, rgb.r, 0];
+        }
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
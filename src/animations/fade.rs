@@ -0,0 +1,82 @@
+use rs_ws281x::RawColor;
+
+use super::{
+    canvas::Canvas,
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::leds::mapping::Key;
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 1] = [ParameterSchema {
+    name: "length",
+    description: "Seconds a released key takes to fade out",
+    kind: ParameterKind::Float,
+    min: 0.05,
+    max: 10.0,
+    default: 1.5,
+}];
+
+#[derive(Debug)]
+pub struct Fade {
+    pub key: Key,
+    pub color: RawColor,
+    /// Seconds left before the key is dark
+    pub remaining: f32,
+    pub started: bool,
+}
+#[derive(Debug)]
+pub struct Fades {
+    pub fades: Vec<Fade>,
+    pub parameters: Parameters,
+}
+impl Fades {
+    pub fn new(config: &Config) -> Fades {
+        Fades {
+            fades: Vec::new(),
+            parameters: Parameters::new(&SCHEMA, config, "fade"),
+        }
+    }
+    fn length(&self) -> f32 {
+        self.parameters.get("length")
+    }
+}
+impl Animation for Fades {
+    fn note_on(&mut self, key: &Key, color: RawColor, _velocity: u8) {
+        self.fades.push(Fade {
+            key: key.clone(),
+            color,
+            remaining: self.length(),
+            started: false,
+        });
+    }
+    fn note_off(&mut self, key: &Key, _color: RawColor) {
+        for fade in self.fades.iter_mut() {
+            if fade.key.note == key.note {
+                fade.started = true;
+            }
+        }
+    }
+    fn update(&mut self, delta: f32) {
+        for fade in self.fades.iter_mut() {
+            if fade.started {
+                fade.remaining -= delta;
+            }
+        }
+        self.fades.retain(|fade| fade.remaining > 0.0);
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let length = self.length();
+        let mut canvas = Canvas::new(leds.len());
+        for fade in self.fades.iter() {
+            canvas.add_key(&fade.key, fade.color, (fade.remaining / length).min(1.0));
+        }
+        canvas.draw(leds);
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
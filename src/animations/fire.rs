@@ -0,0 +1,163 @@
+use rand::prelude::*;
+use rs_ws281x::RawColor;
+
+use super::{
+    canvas::{mix, Canvas},
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::leds::mapping::Key;
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 3] = [
+    ParameterSchema {
+        name: "height",
+        description: "Height of the flames on each side of their key, in LEDs",
+        kind: ParameterKind::Integer,
+        min: 2.0,
+        max: 60.0,
+        default: 12.0,
+    },
+    ParameterSchema {
+        name: "cooling",
+        description: "How fast the flames cool down, in percent",
+        kind: ParameterKind::Integer,
+        min: 0.0,
+        max: 100.0,
+        default: 55.0,
+    },
+    ParameterSchema {
+        name: "sparking",
+        description: "Chance of a new spark at the base of a burning flame, in percent",
+        kind: ParameterKind::Integer,
+        min: 0.0,
+        max: 100.0,
+        default: 60.0,
+    },
+];
+
+/// The flames are simulated at a fixed rate, whatever the frame rate
+const STEP: f32 = 1.0 / 60.0;
+
+const WHITE: RawColor = [255, 255, 255, 0];
+const BLACK: RawColor = [0, 0, 0, 0];
+
+#[derive(Debug)]
+pub struct Flame {
+    pub position: usize,
+    pub color: RawColor,
+    /// Heat of each LED of the flame, from its base, between 0 and 1
+    pub heat: Vec<f32>,
+    /// Sparks keep being added while the key is held
+    pub burning: bool,
+    pub intensity: f32,
+}
+#[derive(Debug)]
+pub struct Fire {
+    pub flames: Vec<Flame>,
+    /// Seconds not simulated yet, less than a step
+    pub pending: f32,
+    pub parameters: Parameters,
+}
+impl Fire {
+    pub fn new(config: &Config) -> Fire {
+        Fire {
+            flames: Vec::new(),
+            pending: 0.0,
+            parameters: Parameters::new(&SCHEMA, config, "fire"),
+        }
+    }
+    /// Cools the flames, lets the heat rise and adds sparks at their base
+    fn step(&mut self) {
+        let height = self.parameters.get("height") as usize;
+        let cooling = self.parameters.get("cooling");
+        let sparking = self.parameters.get("sparking") / 100.0;
+        let max_cooling = (cooling * 10.0 / height as f32 + 2.0) / 255.0;
+        let mut rng = rand::thread_rng();
+        for flame in self.flames.iter_mut() {
+            flame.heat.resize(height, 0.0);
+            for heat in flame.heat.iter_mut() {
+                *heat = (*heat - rng.gen_range(0.0..max_cooling)).max(0.0);
+            }
+            for k in (2..height).rev() {
+                flame.heat[k] = (flame.heat[k - 1] + 2.0 * flame.heat[k - 2]) / 3.0;
+            }
+            if flame.burning && rng.gen::<f32>() < sparking {
+                let k = rng.gen_range(0..height.min(3));
+                flame.heat[k] =
+                    (flame.heat[k] + rng.gen_range(0.6..1.0) * flame.intensity).min(1.0);
+            }
+        }
+        self.flames
+            .retain(|flame| flame.burning || flame.heat.iter().any(|heat| *heat > 0.0));
+    }
+}
+/// Black at the top of the flame, the color of the key in its body and
+/// close to white at its base
+fn get_flame_color(color: RawColor, heat: f32) -> RawColor {
+    if heat < 0.5 {
+        mix(BLACK, color, heat * 2.0)
+    } else {
+        mix(color, WHITE, heat - 0.5)
+    }
+}
+impl Animation for Fire {
+    fn note_on(&mut self, key: &Key, color: RawColor, velocity: u8) {
+        let position = key.center();
+        let intensity = 0.3 + 0.7 * velocity.min(127) as f32 / 127.0;
+        match self
+            .flames
+            .iter_mut()
+            .find(|flame| flame.position == position)
+        {
+            Some(flame) => {
+                flame.color = color;
+                flame.burning = true;
+                flame.intensity = intensity;
+            }
+            None => self.flames.push(Flame {
+                position,
+                color,
+                heat: Vec::new(),
+                burning: true,
+                intensity,
+            }),
+        }
+    }
+    fn note_off(&mut self, key: &Key, _color: RawColor) {
+        let position = key.center();
+        for flame in self.flames.iter_mut() {
+            if flame.position == position {
+                flame.burning = false;
+            }
+        }
+    }
+    fn update(&mut self, delta: f32) {
+        self.pending += delta;
+        while self.pending >= STEP {
+            self.step();
+            self.pending -= STEP;
+        }
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let mut canvas = Canvas::new(leds.len());
+        for flame in self.flames.iter() {
+            for (k, heat) in flame.heat.iter().enumerate() {
+                let color = get_flame_color(flame.color, *heat);
+                canvas.add(flame.position + k, color, 1.0);
+                if k > 0 {
+                    if let Some(position) = flame.position.checked_sub(k) {
+                        canvas.add(position, color, 1.0);
+                    }
+                }
+            }
+        }
+        canvas.draw(leds);
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+use rs_ws281x::RawColor;
+
+use super::{
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::leds::mapping::{Key, KeyMap};
+use crate::leds::state::LedHandle;
+use crate::stats::StatsStore;
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 2] = [
+    ParameterSchema {
+        name: "window",
+        description: "Days of stored stats to start from, 0 for the current session only",
+        kind: ParameterKind::Integer,
+        min: 0.0,
+        max: 365.0,
+        default: 0.0,
+    },
+    ParameterSchema {
+        name: "decay",
+        description: "Heat every key keeps after a minute",
+        kind: ParameterKind::Float,
+        min: 0.001,
+        max: 1.0,
+        default: 0.2,
+    },
+];
+
+/// Asks the hits of each note over the last days of stored stats for a
+/// layer, by its id
+pub type HistoryRequest = (usize, u64);
+
+/// Colors each LED by how often its key has been played, from cold (unused)
+/// to hot (most played).
+#[derive(Debug)]
+pub struct Heatmap {
+    pub heat: Vec<f32>,
+    pub parameters: Parameters,
+    /// Window of the stats last asked for
+    requested: Option<u64>,
+}
+impl Heatmap {
+    /// Starts cold, the stored stats being asked for on the first frame
+    pub fn new(config: &Config) -> Heatmap {
+        Heatmap {
+            heat: vec![0.0; config.leds.num_leds],
+            parameters: Parameters::new(&SCHEMA, config, "heatmap"),
+            requested: None,
+        }
+    }
+    fn window(&self) -> u64 {
+        self.parameters.get("window") as u64
+    }
+}
+impl Animation for Heatmap {
+    fn note_on(&mut self, key: &Key, _color: RawColor, _velocity: u8) {
+        add_heat(&mut self.heat, key, 1.0);
+    }
+    fn update(&mut self, delta: f32) {
+        let decay = self.parameters.get("decay").powf(delta / 60.0);
+        for value in self.heat.iter_mut() {
+            *value *= decay;
+        }
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let max = self.heat.iter().cloned().fold(0.0, f32::max);
+        for (led, value) in leds.iter_mut().zip(self.heat.iter()) {
+            *led = if max > 0.0 {
+                get_heat_color(value / max)
+            } else {
+                [0, 0, 0, 0]
+            };
+        }
+    }
+    fn history_request(&mut self) -> Option<u64> {
+        let window = self.window();
+        if self.requested == Some(window) {
+            return None;
+        }
+        self.requested = Some(window);
+        Some(window)
+    }
+    fn set_history(&mut self, window: u64, key_hits: &[u32], key_map: &KeyMap) {
+        // Answers an older window, the current one is on its way
+        if window != self.window() {
+            return;
+        }
+        self.heat.fill(0.0);
+        let played = key_hits.iter().enumerate().filter(|(_, hits)| **hits > 0);
+        for (note, hits) in played {
+            if let Some(key) = key_map.key(note as u8) {
+                add_heat(&mut self.heat, key, *hits as f32);
+            }
+        }
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
+/// Answers the requests of the heatmaps from the stats store, off the LED
+/// thread which would otherwise wait on it
+pub fn serve_history(
+    stats: &Arc<Mutex<StatsStore>>,
+    leds: &LedHandle,
+    requests: Receiver<HistoryRequest>,
+) {
+    for (id, window) in requests {
+        let key_hits = stats
+            .lock()
+            .expect("Couldn't lock the stats")
+            .recent_key_hits(window);
+        leds.send(move |leds| {
+            leds.animator
+                .set_history(id, window, &key_hits, &leds.key_map)
+        });
+    }
+}
+/// Heats the LEDs of a key, by the part of them it covers
+fn add_heat(heat: &mut [f32], key: &Key, amount: f32) {
+    for position in key.span() {
+        if let Some(value) = heat.get_mut(position) {
+            *value += amount * key.coverage(position);
+        }
+    }
+}
+/// Maps a heat between 0 and 1 to a black-blue-red-yellow gradient
+pub fn get_heat_color(heat: f32) -> RawColor {
+    let stops: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 255.0],
+        [255.0, 0.0, 0.0],
+        [255.0, 255.0, 0.0],
+    ];
+    let scaled = heat.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let index = (scaled as usize).min(stops.len() - 2);
+    let t = scaled - index as f32;
+    let rgb: Vec<u8> = (0..3)
+        .map(|i| (stops[index][i] + (stops[index + 1][i] - stops[index][i]) * t) as u8)
+        .collect();
+    [rgb[2], rgb[1], rgb[0], 0]
+}
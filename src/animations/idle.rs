@@ -0,0 +1,36 @@
+use std::time::{Duration, Instant};
+
+use crate::structs::Config;
+
+/// Watches the note stream, to switch to an ambient animation when nobody
+/// has played for a while
+#[derive(Debug)]
+pub struct Idle {
+    /// Zero never switches to the ambient animation
+    pub timeout: Duration,
+    pub animation: String,
+    pub last_note: Instant,
+    /// Animation to go back to on the next note, only set while idle
+    pub performance: Option<String>,
+}
+impl Idle {
+    pub fn new(config: &Config) -> Idle {
+        Idle {
+            timeout: Duration::from_secs(config.leds.idle.timeout),
+            animation: config.leds.idle.animation.to_string(),
+            last_note: Instant::now(),
+            performance: None,
+        }
+    }
+    pub fn is_idle(&self) -> bool {
+        self.performance.is_some()
+    }
+    pub fn timed_out(&self) -> bool {
+        !self.is_idle() && !self.timeout.is_zero() && self.last_note.elapsed() >= self.timeout
+    }
+    /// Records some activity, returning the animation to go back to if idle
+    pub fn wake(&mut self) -> Option<String> {
+        self.last_note = Instant::now();
+        self.performance.take()
+    }
+}
@@ -0,0 +1,213 @@
+use rand::prelude::*;
+use rs_ws281x::RawColor;
+
+use super::{
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::leds::mapping::Key;
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 5] = [
+    ParameterSchema {
+        name: "chord_size",
+        description: "Notes struck together that make a chord",
+        kind: ParameterKind::Integer,
+        min: 2.0,
+        max: 6.0,
+        default: 3.0,
+    },
+    ParameterSchema {
+        name: "chord_window",
+        description: "Milliseconds within which the notes of a chord must be struck",
+        kind: ParameterKind::Integer,
+        min: 10.0,
+        max: 500.0,
+        default: 80.0,
+    },
+    ParameterSchema {
+        name: "meteors",
+        description: "Meteors sent from each note of a chord",
+        kind: ParameterKind::Integer,
+        min: 1.0,
+        max: 10.0,
+        default: 3.0,
+    },
+    ParameterSchema {
+        name: "speed",
+        description: "Average LEDs travelled by a meteor each second",
+        kind: ParameterKind::Float,
+        min: 10.0,
+        max: 600.0,
+        default: 120.0,
+    },
+    ParameterSchema {
+        name: "decay",
+        description: "Brightness the trails keep after a second, on average",
+        kind: ParameterKind::Float,
+        min: 0.001,
+        max: 0.9,
+        default: 0.01,
+    },
+];
+
+#[derive(Debug)]
+pub struct Meteor {
+    pub position: f32,
+    /// LEDs travelled each second, negative towards the start of the strip
+    pub velocity: f32,
+    pub color: RawColor,
+}
+#[derive(Debug)]
+pub struct Meteors {
+    pub meteors: Vec<Meteor>,
+    /// Left behind by the meteors, faded out randomly so that they crumble
+    pub trails: Vec<RawColor>,
+    /// Notes struck lately, with the time they were struck at
+    pub recent: Vec<(f32, usize, RawColor)>,
+    /// Seconds since the animation started
+    pub time: f32,
+    pub parameters: Parameters,
+}
+impl Meteors {
+    pub fn new(config: &Config) -> Meteors {
+        Meteors {
+            meteors: Vec::new(),
+            trails: vec![[0, 0, 0, 0]; config.leds.num_leds],
+            recent: Vec::new(),
+            time: 0.0,
+            parameters: Parameters::new(&SCHEMA, config, "meteor"),
+        }
+    }
+    /// Sends meteors to both sides of each note of the chord
+    fn shower(&mut self) {
+        let meteors = self.parameters.get("meteors") as usize;
+        let speed = self.parameters.get("speed");
+        let mut rng = rand::thread_rng();
+        for (_, position, color) in self.recent.drain(..) {
+            for _ in 0..meteors {
+                let direction = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+                self.meteors.push(Meteor {
+                    position: position as f32,
+                    velocity: direction * speed * rng.gen_range(0.5..1.5),
+                    color,
+                });
+            }
+        }
+    }
+}
+impl Animation for Meteors {
+    fn note_on(&mut self, key: &Key, color: RawColor, _velocity: u8) {
+        let position = key.center();
+        let chord_size = self.parameters.get("chord_size") as usize;
+        let chord_window = self.parameters.get("chord_window") / 1000.0;
+        let time = self.time;
+        self.recent
+            .retain(|(struck, _, _)| time - struck < chord_window);
+        self.recent.push((time, position, color));
+        if self.recent.len() >= chord_size {
+            self.shower();
+        }
+    }
+    fn update(&mut self, delta: f32) {
+        let decay = self.parameters.get("decay");
+        let mut rng = rand::thread_rng();
+        self.time += delta;
+
+        // Each LED fades at its own pace
+        for led in self.trails.iter_mut() {
+            let factor = decay.powf(delta * rng.gen_range(0.0..2.0));
+            *led = led.map(|channel| (channel as f32 * factor) as u8);
+        }
+        let num_leds = self.trails.len() as f32;
+        for meteor in self.meteors.iter_mut() {
+            // Every LED crossed during the frame is lit, even at high speeds
+            let from = meteor.position;
+            meteor.position += meteor.velocity * delta;
+            let (start, end) = if from < meteor.position {
+                (from, meteor.position)
+            } else {
+                (meteor.position, from)
+            };
+            // From the LED under one end to the LED under the other, the
+            // part out of the strip left out
+            let first = start.floor().max(0.0);
+            let last = end.floor().min(num_leds - 1.0);
+            if first <= last {
+                for led in self.trails[first as usize..=last as usize].iter_mut() {
+                    *led = meteor.color;
+                }
+            }
+        }
+        self.meteors
+            .retain(|meteor| meteor.position >= 0.0 && meteor.position < num_leds);
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        for (led, trail) in leds.iter_mut().zip(self.trails.iter()) {
+            *led = *trail;
+        }
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animations::parameters::Parameter;
+
+    const RED: RawColor = [0, 0, 200, 0];
+
+    fn animation(num_leds: usize, meteors: &[(f32, f32)]) -> Meteors {
+        Meteors {
+            meteors: meteors
+                .iter()
+                .map(|(position, velocity)| Meteor {
+                    position: *position,
+                    velocity: *velocity,
+                    color: RED,
+                })
+                .collect(),
+            trails: vec![[0, 0, 0, 0]; num_leds],
+            recent: Vec::new(),
+            time: 0.0,
+            parameters: Parameters(
+                SCHEMA
+                    .iter()
+                    .map(|schema| Parameter {
+                        schema: *schema,
+                        value: schema.default,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+    fn lit(meteors: &Meteors) -> Vec<usize> {
+        (0..meteors.trails.len())
+            .filter(|led| meteors.trails[*led] != [0, 0, 0, 0])
+            .collect()
+    }
+
+    #[test]
+    fn lights_every_led_crossed() {
+        let mut meteors = animation(20, &[(5.5, -4.0)]);
+        meteors.update(0.5);
+        assert_eq!(lit(&meteors), [3, 4, 5]);
+
+        let mut meteors = animation(20, &[(5.5, 4.0)]);
+        meteors.update(0.5);
+        assert_eq!(lit(&meteors), [5, 6, 7]);
+    }
+
+    #[test]
+    fn lights_up_to_the_ends_of_the_strip() {
+        let mut meteors = animation(20, &[(1.5, -40.0), (18.5, 40.0)]);
+        meteors.update(0.5);
+        assert_eq!(lit(&meteors), [0, 1, 18, 19]);
+        assert!(meteors.meteors.is_empty());
+    }
+}
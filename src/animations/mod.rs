@@ -0,0 +1,190 @@
+pub mod aurora;
+pub mod breathing;
+pub mod canvas;
+pub mod comet;
+pub mod compositor;
+pub mod default;
+pub mod drift;
+pub mod fade;
+pub mod fire;
+pub mod heatmap;
+pub mod idle;
+pub mod meteor;
+pub mod parameters;
+pub mod ripple;
+pub mod sparkle;
+pub mod static_color;
+pub mod transition;
+
+use rocket::serde::Serialize;
+use rs_ws281x::RawColor;
+
+use crate::leds::mapping::{Key, KeyMap};
+use crate::structs::{ColorMode, Config};
+use parameters::{ParameterSchema, Parameters};
+
+/// A single animation of the strip. The `Animator` forwards the note events
+/// to the active animation, then updates and draws it once per frame.
+/// Animations are driven by the elapsed time, not by the frame count, so
+/// that their speed doesn't depend on the frame rate.
+pub trait Animation: Send {
+    /// Called when the animation becomes the active one
+    fn start(&mut self) {}
+    /// Called when another animation replaces this one
+    fn stop(&mut self) {}
+    /// Called when the color mode changes, for animations that aren't only
+    /// colored by the notes
+    fn set_color_mode(&mut self, _color_mode: &ColorMode) {}
+    /// `key` is the part of the strip facing the played key, `velocity` the
+    /// one of the MIDI note, between 0 and 127
+    fn note_on(&mut self, _key: &Key, _color: RawColor, _velocity: u8) {}
+    fn note_off(&mut self, _key: &Key, _color: RawColor) {}
+    /// Advances the animation by `delta` seconds, the time since the
+    /// previous frame
+    fn update(&mut self, _delta: f32) {}
+    /// Days of stored stats whose hits the animation starts from, asked
+    /// once each time they change. 0 is for the session being played only.
+    fn history_request(&mut self) -> Option<u64> {
+        None
+    }
+    /// Hits of each MIDI note over the days asked for
+    fn set_history(&mut self, _window: u64, _key_hits: &[u32], _key_map: &KeyMap) {}
+    /// Draws the current state of the animation on the LEDs of the strip
+    fn draw(&mut self, leds: &mut [RawColor]);
+    /// Typed parameters of the animation, read at every frame
+    fn parameters(&self) -> &Parameters;
+    fn parameters_mut(&mut self) -> &mut Parameters;
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(crate = "rocket::serde")]
+pub struct AnimationInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+struct Registered {
+    info: AnimationInfo,
+    parameters: &'static [ParameterSchema],
+    create: fn(&Config) -> Box<dyn Animation>,
+}
+
+/// Every animation that can be selected by name. Adding an animation only
+/// takes a new module and an entry here.
+const ANIMATIONS: &[Registered] = &[
+    Registered {
+        info: AnimationInfo {
+            name: "default",
+            description: "Lights the keys while they are held",
+        },
+        parameters: &[],
+        create: |config| Box::new(default::DefaultAnimator::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "fade",
+            description: "Fades the keys out once they are released",
+        },
+        parameters: &fade::SCHEMA,
+        create: |config| Box::new(fade::Fades::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "ripple",
+            description: "Sends ripples to both sides of the pressed keys",
+        },
+        parameters: &ripple::SCHEMA,
+        create: |config| Box::new(ripple::Ripples::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "static",
+            description: "Fills the strip with the configured color",
+        },
+        parameters: &[],
+        create: |config| Box::new(static_color::StaticColor::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "heatmap",
+            description: "Colors each key by how often it has been played",
+        },
+        parameters: &heatmap::SCHEMA,
+        create: |config| Box::new(heatmap::Heatmap::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "fire",
+            description: "Flames rise from the struck keys while they are held",
+        },
+        parameters: &fire::SCHEMA,
+        create: |config| Box::new(fire::Fire::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "sparkle",
+            description: "Sparkles twinkle around the held keys",
+        },
+        parameters: &sparkle::SCHEMA,
+        create: |config| Box::new(sparkle::Sparkles::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "comet",
+            description: "Launches comets in the direction of the melody",
+        },
+        parameters: &comet::SCHEMA,
+        create: |config| Box::new(comet::Comets::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "meteor",
+            description: "Sends meteor showers from the notes of the chords",
+        },
+        parameters: &meteor::SCHEMA,
+        create: |config| Box::new(meteor::Meteors::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "breathing",
+            description: "Slowly brightens and dims the colors of the color mode",
+        },
+        parameters: &breathing::SCHEMA,
+        create: |config| Box::new(breathing::Breathing::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "drift",
+            description: "A dim rainbow slowly drifting along the strip",
+        },
+        parameters: &drift::SCHEMA,
+        create: |config| Box::new(drift::Drift::new(config)),
+    },
+    Registered {
+        info: AnimationInfo {
+            name: "aurora",
+            description: "Curtains of green and purple light waving along the strip",
+        },
+        parameters: &aurora::SCHEMA,
+        create: |config| Box::new(aurora::Aurora::new(config)),
+    },
+];
+
+pub fn get_animations() -> Vec<AnimationInfo> {
+    ANIMATIONS.iter().map(|animation| animation.info).collect()
+}
+
+/// Parameters of an animation, none for unknown animations
+pub fn get_parameter_schema(name: &str) -> Option<&'static [ParameterSchema]> {
+    ANIMATIONS
+        .iter()
+        .find(|animation| animation.info.name == name)
+        .map(|animation| animation.parameters)
+}
+
+pub fn create_animation(name: &str, config: &Config) -> Option<Box<dyn Animation>> {
+    ANIMATIONS
+        .iter()
+        .find(|animation| animation.info.name == name)
+        .map(|animation| (animation.create)(config))
+}
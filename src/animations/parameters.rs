@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use paris::warn;
+use rocket::serde::Serialize;
+
+use crate::structs::Config;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum ParameterKind {
+    Integer,
+    Float,
+}
+
+/// Describes a parameter of an animation, so that it can be validated and
+/// the web UI can build a control for it.
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(crate = "rocket::serde")]
+pub struct ParameterSchema {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: ParameterKind,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+impl ParameterSchema {
+    pub fn validate(&self, value: f32) -> Result<f32, String> {
+        if !value.is_finite() || value < self.min || value > self.max {
+            return Err(format!(
+                "`{}` must be between {} and {}, got {}",
+                self.name, self.min, self.max, value
+            ));
+        }
+        if self.kind == ParameterKind::Integer && value.fract() != 0.0 {
+            return Err(format!("`{}` must be an integer, got {}", self.name, value));
+        }
+        Ok(value)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Parameter {
+    #[serde(flatten)]
+    pub schema: ParameterSchema,
+    pub value: f32,
+}
+
+/// Current values of the parameters of an animation
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct Parameters(pub Vec<Parameter>);
+impl Parameters {
+    /// Starts from the defaults of the schema, overridden by the values of
+    /// `[leds.animations.<animation>]` in the config.
+    pub fn new(schema: &[ParameterSchema], config: &Config, animation: &str) -> Parameters {
+        let values = config.leds.animations.get(animation);
+        Parameters(
+            schema
+                .iter()
+                .map(|schema| {
+                    let value = match values.and_then(|values| values.get(schema.name)) {
+                        Some(value) => schema.validate(*value).unwrap_or_else(|e| {
+                            warn!("<yellow>[WS2812]</> {}, using {}", e, schema.default);
+                            schema.default
+                        }),
+                        None => schema.default,
+                    };
+                    Parameter {
+                        schema: *schema,
+                        value,
+                    }
+                })
+                .collect(),
+        )
+    }
+    pub fn get(&self, name: &str) -> f32 {
+        self.0
+            .iter()
+            .find(|parameter| parameter.schema.name == name)
+            .map(|parameter| parameter.value)
+            .unwrap_or_else(|| panic!("Unknown parameter `{}`", name))
+    }
+    pub fn validate(&self, name: &str, value: f32) -> Result<f32, String> {
+        self.0
+            .iter()
+            .find(|parameter| parameter.schema.name == name)
+            .ok_or_else(|| format!("Unknown parameter `{}`", name))?
+            .schema
+            .validate(value)
+    }
+    /// Sets several parameters at once, only if all of them are valid
+    pub fn set_all(&mut self, values: &HashMap<String, f32>) -> Result<(), Vec<String>> {
+        let errors: Vec<String> = values
+            .iter()
+            .filter_map(|(name, value)| self.validate(name, *value).err())
+            .collect();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        for (name, value) in values.iter() {
+            self.set(name, *value)
+                .expect("Parameter should have been validated");
+        }
+        Ok(())
+    }
+    pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+        let value = self.validate(name, value)?;
+        if let Some(parameter) = self
+            .0
+            .iter_mut()
+            .find(|parameter| parameter.schema.name == name)
+        {
+            parameter.value = value;
+        }
+        Ok(())
+    }
+}
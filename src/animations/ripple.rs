@@ -0,0 +1,296 @@
+use rs_ws281x::RawColor;
+
+use super::{
+    canvas::Canvas,
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::leds::mapping::Key;
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 5] = [
+    ParameterSchema {
+        name: "speed",
+        description: "LEDs travelled by a ripple each second",
+        kind: ParameterKind::Float,
+        min: 1.0,
+        max: 600.0,
+        default: 60.0,
+    },
+    ParameterSchema {
+        name: "trail_length",
+        description: "Length of the trail behind a ripple, in LEDs",
+        kind: ParameterKind::Integer,
+        min: 1.0,
+        max: 50.0,
+        default: 10.0,
+    },
+    ParameterSchema {
+        name: "trail_fade",
+        description: "How much the trail fades out towards its end, in percent",
+        kind: ParameterKind::Integer,
+        min: 0.0,
+        max: 100.0,
+        default: 100.0,
+    },
+    ParameterSchema {
+        name: "decay",
+        description: "Amplitude a ripple keeps after a second",
+        kind: ParameterKind::Float,
+        min: 0.001,
+        max: 1.0,
+        default: 0.16,
+    },
+    ParameterSchema {
+        name: "velocity",
+        description:
+            "How much the velocity of a note drives the amplitude of its ripple, in percent",
+        kind: ParameterKind::Integer,
+        min: 0.0,
+        max: 100.0,
+        default: 100.0,
+    },
+];
+
+/// Below this, a ripple wouldn't light a LED anymore
+const MIN_AMPLITUDE: f32 = 1.0 / 255.0;
+
+#[derive(Debug)]
+pub struct Ripple {
+    pub origin: f32,
+    /// Distance the heads of the ripple travelled from `origin`
+    pub distance: f32,
+    /// Brightness of the heads, between 0 and 1
+    pub amplitude: f32,
+    pub color: RawColor,
+}
+impl Ripple {
+    /// Brightness of the ripple at `position`. Both sides are a head followed
+    /// by a trail fading back towards the origin, the LED right ahead of a
+    /// head is partly lit so that sub-pixel moves stay smooth.
+    fn intensity(&self, position: f32, trail_length: f32, trail_fade: f32) -> f32 {
+        let behind = self.distance - (position - self.origin).abs();
+        if !(-1.0..=trail_length).contains(&behind) {
+            return 0.0;
+        }
+        let weight = if behind < 0.0 {
+            1.0 + behind
+        } else {
+            1.0 - trail_fade * behind / trail_length
+        };
+        weight * self.amplitude
+    }
+}
+#[derive(Debug)]
+pub struct Ripples {
+    pub num_leds: usize,
+    pub ripples: Vec<Ripple>,
+    pub parameters: Parameters,
+}
+
+impl Ripples {
+    pub fn new(config: &Config) -> Ripples {
+        Ripples {
+            num_leds: config.leds.num_leds,
+            ripples: Vec::new(),
+            parameters: Parameters::new(&SCHEMA, config, "ripple"),
+        }
+    }
+}
+impl Animation for Ripples {
+    fn note_on(&mut self, key: &Key, color: RawColor, velocity: u8) {
+        let position = key.center();
+        let sensitivity = self.parameters.get("velocity") / 100.0;
+        self.ripples.push(Ripple {
+            origin: position as f32,
+            distance: 0.0,
+            amplitude: 1.0 - sensitivity * (1.0 - velocity.min(127) as f32 / 127.0),
+            color,
+        });
+    }
+    fn update(&mut self, delta: f32) {
+        let speed = self.parameters.get("speed") * delta;
+        let decay = self.parameters.get("decay").powf(delta);
+        let trail_length = self.parameters.get("trail_length");
+        for ripple in self.ripples.iter_mut() {
+            ripple.distance += speed;
+            ripple.amplitude *= decay;
+        }
+        // A ripple is over once it faded out or the end of both its trails
+        // left the strip
+        let num_leds = self.num_leds as f32;
+        self.ripples.retain(|ripple| {
+            let tail = ripple.distance - trail_length;
+            ripple.amplitude >= MIN_AMPLITUDE
+                && (tail <= ripple.origin || ripple.origin + tail < num_leds)
+        });
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let trail_length = self.parameters.get("trail_length");
+        let trail_fade = self.parameters.get("trail_fade") / 100.0;
+        let mut canvas = Canvas::new(leds.len());
+        for ripple in self.ripples.iter() {
+            let start = (ripple.origin - ripple.distance - 1.0).max(0.0) as usize;
+            let end = (ripple.origin + ripple.distance + 1.0) as usize;
+            for position in start..=end.min(leds.len()) {
+                let intensity = ripple.intensity(position as f32, trail_length, trail_fade);
+                canvas.add(position, ripple.color, intensity);
+            }
+        }
+        canvas.draw(leds);
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animations::parameters::Parameter;
+
+    const RED: RawColor = [0, 0, 200, 0];
+    /// Makes a ripple travel 1 LED at the speed of the tests
+    const FRAME: f32 = 0.25;
+
+    fn animation(num_leds: usize, values: &[(&str, f32)]) -> Ripples {
+        let mut ripples = Ripples {
+            num_leds,
+            ripples: Vec::new(),
+            parameters: Parameters(
+                SCHEMA
+                    .iter()
+                    .map(|schema| Parameter {
+                        schema: *schema,
+                        value: schema.default,
+                    })
+                    .collect(),
+            ),
+        };
+        ripples
+            .parameters
+            .set("decay", 1.0)
+            .expect("Invalid parameter");
+        ripples
+            .parameters
+            .set("speed", 4.0)
+            .expect("Invalid parameter");
+        for (name, value) in values {
+            ripples
+                .parameters
+                .set(name, *value)
+                .expect("Invalid parameter");
+        }
+        ripples
+    }
+    /// Key facing a single LED
+    fn key(led: usize) -> Key {
+        Key {
+            note: 60,
+            leds: vec![led],
+            start: led as f32,
+            end: led as f32 + 1.0,
+            brightness: 1.0,
+        }
+    }
+    fn frame(ripples: &mut Ripples) -> Vec<RawColor> {
+        let mut leds = vec![[1, 1, 1, 1]; ripples.num_leds];
+        ripples.draw(&mut leds);
+        leds
+    }
+
+    #[test]
+    fn lights_the_origin_first() {
+        let mut ripples = animation(20, &[]);
+        ripples.note_on(&key(10), RED, 127);
+        let leds = frame(&mut ripples);
+        assert_eq!(leds[10], RED);
+        assert!(leds
+            .iter()
+            .enumerate()
+            .all(|(i, led)| i == 10 || *led == [0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn travels_to_both_sides_with_a_fading_trail() {
+        let mut ripples = animation(20, &[("trail_length", 4.0), ("trail_fade", 100.0)]);
+        ripples.note_on(&key(10), RED, 127);
+        for _ in 0..3 {
+            ripples.update(FRAME);
+        }
+        let leds = frame(&mut ripples);
+        assert_eq!(leds[13], RED);
+        assert_eq!(leds[7], RED);
+        assert_eq!(leds[12], [0, 0, 150, 0]);
+        assert_eq!(leds[8], [0, 0, 150, 0]);
+        assert_eq!(leds[10], [0, 0, 50, 0]);
+        assert_eq!(leds[14], [0, 0, 0, 0]);
+        assert_eq!(leds[6], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn spreads_sub_pixel_moves() {
+        let mut ripples = animation(20, &[("speed", 2.0), ("trail_length", 1.0)]);
+        ripples.note_on(&key(10), RED, 127);
+        ripples.update(FRAME);
+        let leds = frame(&mut ripples);
+        assert_eq!(leds[9], [0, 0, 100, 0]);
+        assert_eq!(leds[10], [0, 0, 100, 0]);
+        assert_eq!(leds[11], [0, 0, 100, 0]);
+    }
+
+    #[test]
+    fn scales_with_velocity() {
+        let mut ripples = animation(20, &[]);
+        ripples.note_on(&key(10), RED, 0);
+        assert_eq!(frame(&mut ripples)[10], [0, 0, 0, 0]);
+
+        let mut ripples = animation(20, &[("velocity", 50.0)]);
+        ripples.note_on(&key(10), RED, 0);
+        assert_eq!(frame(&mut ripples)[10], [0, 0, 100, 0]);
+    }
+
+    #[test]
+    fn adds_up_where_waves_meet() {
+        let mut ripples = animation(20, &[("trail_length", 1.0)]);
+        ripples.note_on(&key(8), RED, 127);
+        ripples.note_on(&key(12), [0, 100, 0, 0], 127);
+        ripples.update(FRAME);
+        ripples.update(FRAME);
+        let leds = frame(&mut ripples);
+        assert_eq!(leds[10], [0, 100, 200, 0]);
+
+        ripples.note_on(&key(10), RED, 127);
+        assert_eq!(frame(&mut ripples)[10], [0, 100, 255, 0]);
+    }
+
+    #[test]
+    fn removes_ripples_out_of_the_strip() {
+        let mut ripples = animation(20, &[("trail_length", 5.0)]);
+        ripples.note_on(&key(15), RED, 127);
+        for _ in 0..20 {
+            ripples.update(FRAME);
+        }
+        assert_eq!(ripples.ripples.len(), 1);
+        ripples.update(FRAME);
+        assert!(ripples.ripples.is_empty());
+        assert!(frame(&mut ripples).iter().all(|led| *led == [0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn removes_faded_ripples() {
+        let mut ripples = animation(200, &[("decay", 0.5)]);
+        ripples.note_on(&key(100), RED, 127);
+        for _ in 0..30 {
+            ripples.update(FRAME);
+        }
+        assert_eq!(ripples.ripples.len(), 1);
+        for _ in 0..30 {
+            ripples.update(FRAME);
+        }
+        assert!(ripples.ripples.is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use rand::prelude::*;
+use rs_ws281x::RawColor;
+
+use super::{
+    canvas::Canvas,
+    parameters::{ParameterKind, ParameterSchema, Parameters},
+    Animation,
+};
+use crate::leds::mapping::Key;
+use crate::structs::Config;
+
+pub const SCHEMA: [ParameterSchema; 3] = [
+    ParameterSchema {
+        name: "density",
+        description: "Sparkles appearing around a held key each second",
+        kind: ParameterKind::Float,
+        min: 0.5,
+        max: 120.0,
+        default: 18.0,
+    },
+    ParameterSchema {
+        name: "spread",
+        description: "Distance from the edges of their key at which sparkles appear, in LEDs",
+        kind: ParameterKind::Integer,
+        min: 0.0,
+        max: 30.0,
+        default: 4.0,
+    },
+    ParameterSchema {
+        name: "lifetime",
+        description: "Seconds a sparkle takes to twinkle in and out",
+        kind: ParameterKind::Float,
+        min: 0.05,
+        max: 5.0,
+        default: 0.35,
+    },
+];
+
+#[derive(Debug)]
+pub struct Sparkle {
+    pub position: usize,
+    pub color: RawColor,
+    /// Seconds
+    pub age: f32,
+    pub lifetime: f32,
+}
+impl Sparkle {
+    /// Rises to full brightness halfway through its life, then fades out
+    fn brightness(&self) -> f32 {
+        let progress = self.age / self.lifetime;
+        1.0 - (2.0 * progress - 1.0).abs()
+    }
+}
+#[derive(Debug)]
+pub struct Sparkles {
+    /// Keys held down, with their color
+    pub held: Vec<(Key, RawColor)>,
+    pub sparkles: Vec<Sparkle>,
+    pub parameters: Parameters,
+}
+impl Sparkles {
+    pub fn new(config: &Config) -> Sparkles {
+        Sparkles {
+            held: Vec::new(),
+            sparkles: Vec::new(),
+            parameters: Parameters::new(&SCHEMA, config, "sparkle"),
+        }
+    }
+}
+impl Animation for Sparkles {
+    fn note_on(&mut self, key: &Key, color: RawColor, _velocity: u8) {
+        self.held.retain(|(held, _)| held.note != key.note);
+        self.held.push((key.clone(), color));
+    }
+    fn note_off(&mut self, key: &Key, _color: RawColor) {
+        self.held.retain(|(held, _)| held.note != key.note);
+    }
+    fn update(&mut self, delta: f32) {
+        let density = self.parameters.get("density") * delta;
+        let spread = self.parameters.get("spread") as isize;
+        let lifetime = self.parameters.get("lifetime");
+        let mut rng = rand::thread_rng();
+
+        for sparkle in self.sparkles.iter_mut() {
+            sparkle.age += delta;
+        }
+        self.sparkles
+            .retain(|sparkle| sparkle.age < sparkle.lifetime);
+
+        for (key, color) in self.held.iter() {
+            let span = key.span();
+            // Several sparkles can appear during a long frame
+            let count = density.trunc() as usize + rng.gen_bool(density.fract() as f64) as usize;
+            for _ in 0..count {
+                let position =
+                    rng.gen_range(span.start as isize - spread..span.end as isize + spread);
+                if let Ok(position) = usize::try_from(position) {
+                    self.sparkles.push(Sparkle {
+                        position,
+                        color: *color,
+                        age: 0.0,
+                        // Slightly different lifetimes keep them from blinking together
+                        lifetime: rng.gen_range(lifetime / 2.0..=lifetime),
+                    });
+                }
+            }
+        }
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        let mut canvas = Canvas::new(leds.len());
+        for sparkle in self.sparkles.iter() {
+            canvas.add(sparkle.position, sparkle.color, sparkle.brightness());
+        }
+        canvas.draw(leds);
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
@@ -0,0 +1,37 @@
+use rs_ws281x::RawColor;
+
+use super::{parameters::Parameters, Animation};
+use crate::functions::hex_to_rgb;
+use crate::structs::{ColorMode, Config};
+
+pub struct StaticColor {
+    pub colors: Vec<RawColor>,
+    pub parameters: Parameters,
+}
+impl StaticColor {
+    pub fn new(config: &Config) -> Self {
+        let rgb = hex_to_rgb(&config.leds.color_mode);
+        Self {
+            colors: vec![[rgb[2], rgb[1], rgb[0], 0]; config.leds.num_leds],
+            parameters: Parameters::default(),
+        }
+    }
+}
+impl Animation for StaticColor {
+    fn set_color_mode(&mut self, color_mode: &ColorMode) {
+        for (position, color) in self.colors.iter_mut().enumerate() {
+            *color = color_mode.get_color(position, 0);
+        }
+    }
+    fn draw(&mut self, leds: &mut [RawColor]) {
+        for (led, color) in leds.iter_mut().zip(self.colors.iter()) {
+            *led = *color;
+        }
+    }
+    fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+    fn parameters_mut(&mut self) -> &mut Parameters {
+        &mut self.parameters
+    }
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use rs_ws281x::RawColor;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{canvas::mix, Animation};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransitionKind {
+    None,
+    Crossfade,
+    /// The incoming frame sweeps over the outgoing one from the start of the strip
+    Wipe,
+}
+
+/// Blends a layer from what it showed before a switch to what it shows now
+pub struct Transition {
+    /// Frame of the outgoing animation, or a frozen copy of the layer
+    pub from: Vec<RawColor>,
+    /// Keeps running until the transition is over
+    pub outgoing: Option<Box<dyn Animation>>,
+    pub kind: TransitionKind,
+    /// Seconds of frames played since the switch
+    elapsed: f32,
+    duration: Duration,
+}
+impl Transition {
+    pub fn new(
+        from: Vec<RawColor>,
+        outgoing: Option<Box<dyn Animation>>,
+        kind: TransitionKind,
+        duration: Duration,
+    ) -> Transition {
+        Transition {
+            from,
+            outgoing,
+            kind,
+            elapsed: 0.0,
+            duration,
+        }
+    }
+    /// Between 0 (only the outgoing frame) and 1 (only the incoming one)
+    pub fn progress(&self) -> f32 {
+        if self.kind == TransitionKind::None || self.duration.is_zero() {
+            return 1.0;
+        }
+        (self.elapsed / self.duration.as_secs_f32()).min(1.0)
+    }
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+    /// Advances the transition by `delta` seconds, like the animations
+    pub fn update(&mut self, delta: f32) {
+        self.elapsed += delta;
+        if let Some(outgoing) = self.outgoing.as_mut() {
+            outgoing.update(delta);
+            outgoing.draw(&mut self.from);
+        }
+    }
+    /// Mixes the outgoing frame into `frame`, which holds the incoming one
+    pub fn apply(&self, frame: &mut [RawColor]) {
+        let progress = self.progress();
+        let wipe_edge = (progress * frame.len() as f32) as usize;
+        for (i, (led, from)) in frame.iter_mut().zip(self.from.iter()).enumerate() {
+            *led = match self.kind {
+                TransitionKind::None => *led,
+                TransitionKind::Crossfade => mix(*from, *led, progress),
+                TransitionKind::Wipe if i < wipe_edge => *led,
+                TransitionKind::Wipe => *from,
+            };
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use rocket::{
+    serde::{json::Json, Deserialize, Serialize},
+    State,
+};
+
+use super::{
+    error::{parse_body, ApiError, ApiResult, JsonBody},
+    success, AppState, Message,
+};
+use crate::leds::{
+    calibration::{save_key_map, Calibration, CalibrationPoint, CalibrationStep},
+    state::LedState,
+};
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CalibrationState {
+    active: bool,
+    step: Option<CalibrationStep>,
+    /// Key pressed last, lit at the LED it is mapped to
+    current: Option<CalibrationPoint>,
+    /// Confirmed keys, from the lowest one
+    points: Vec<CalibrationPoint>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Nudge {
+    /// LEDs to move by, negative towards the start of the strip
+    leds: i64,
+}
+
+fn get_calibration_state(leds: &LedState) -> CalibrationState {
+    match &leds.calibration {
+        Some(calibration) => CalibrationState {
+            active: true,
+            step: Some(calibration.step()),
+            current: calibration.current,
+            points: calibration.points(),
+        },
+        None => CalibrationState {
+            active: false,
+            step: None,
+            current: None,
+            points: Vec::new(),
+        },
+    }
+}
+fn not_calibrating() -> ApiError {
+    ApiError::conflict("No calibration in progress".to_string())
+}
+/// Runs `change` on the calibration in progress
+async fn update_calibration<F>(state: &State<AppState>, change: F) -> ApiResult<CalibrationState>
+where
+    F: FnOnce(&mut Calibration) -> Result<(), String> + Send + 'static,
+{
+    let calibration = state
+        .leds
+        .run(move |leds| {
+            let calibration = leds.calibration.as_mut().ok_or_else(not_calibrating)?;
+            change(calibration).map_err(ApiError::conflict)?;
+            Ok::<_, ApiError>(get_calibration_state(leds))
+        })
+        .await?;
+    Ok(success("calibration", calibration))
+}
+
+#[get("/calibration")]
+pub async fn get_calibration(state: &State<AppState>) -> Json<Message<CalibrationState>> {
+    success(
+        "calibration",
+        state.leds.run(|leds| get_calibration_state(leds)).await,
+    )
+}
+/// Starts over, the animations stop until the calibration is saved or
+/// cancelled
+#[post("/calibration/start")]
+pub async fn start_calibration(state: &State<AppState>) -> Json<Message<CalibrationState>> {
+    success(
+        "calibration",
+        state
+            .leds
+            .run(|leds| {
+                leds.calibration = Some(Calibration::new(&leds.config));
+                get_calibration_state(leds)
+            })
+            .await,
+    )
+}
+#[post("/calibration/nudge", data = "<nudge>")]
+pub async fn nudge_calibration(
+    state: &State<AppState>,
+    nudge: JsonBody<'_, Nudge>,
+) -> ApiResult<CalibrationState> {
+    let leds = parse_body(nudge)?.leds;
+    update_calibration(state, move |calibration| {
+        calibration.nudge(leds).map(|_| ())
+    })
+    .await
+}
+#[post("/calibration/confirm")]
+pub async fn confirm_calibration(state: &State<AppState>) -> ApiResult<CalibrationState> {
+    update_calibration(state, |calibration| calibration.confirm().map(|_| ())).await
+}
+/// Writes the mapping interpolated from the confirmed keys to the config
+/// file, then applies it. The calibration goes on if it can't be written.
+#[post("/calibration/save")]
+pub async fn save_calibration(state: &State<AppState>) -> ApiResult<CalibrationState> {
+    let key_map = state
+        .leds
+        .run(|leds| {
+            leds.calibration
+                .as_ref()
+                .ok_or_else(not_calibrating)?
+                .key_map()
+                .map_err(ApiError::conflict)
+        })
+        .await?;
+    let config = state
+        .reloader
+        .lock()
+        .expect("Couldn't lock the reloader")
+        .paths()
+        .config
+        .to_string();
+    save_key_map(&config, &key_map).map_err(ApiError::internal)?;
+    let calibration = state
+        .leds
+        .run(move |leds| {
+            leds.key_map = key_map;
+            leds.calibration = None;
+            get_calibration_state(leds)
+        })
+        .await;
+    Ok(success("calibration", calibration))
+}
+/// Stops the calibration, keeping the previous mapping
+#[delete("/calibration")]
+pub async fn cancel_calibration(state: &State<AppState>) -> Json<Message<CalibrationState>> {
+    success(
+        "calibration",
+        state
+            .leds
+            .run(|leds| {
+                leds.calibration = None;
+                get_calibration_state(leds)
+            })
+            .await,
+    )
+}
@@ -0,0 +1,40 @@
+use rocket::{serde::json::Json, tokio::task, State};
+
+use super::{
+    error::{ApiError, ApiResult},
+    success, AppState, Message,
+};
+use crate::reload::ReloadStatus;
+
+/// Outcome of the last reload of the config file, its problems included
+#[get("/config/reload")]
+pub async fn get_reload(state: &State<AppState>) -> Json<Message<ReloadStatus>> {
+    success(
+        "reload",
+        state
+            .reloader
+            .lock()
+            .expect("Couldn't lock the reloader")
+            .status
+            .clone(),
+    )
+}
+/// Reloads the config file now, without waiting for it to be noticed. An
+/// invalid file is an error listing its problems.
+#[post("/config/reload")]
+pub async fn reload_config(state: &State<AppState>) -> ApiResult<ReloadStatus> {
+    let reloader = state.reloader.clone();
+    // Reopening the strip waits on the LED thread
+    let status = task::spawn_blocking(move || {
+        reloader
+            .lock()
+            .expect("Couldn't lock the reloader")
+            .reload()
+    })
+    .await
+    .map_err(|e| ApiError::internal(format!("The reload stopped: {}", e)))?;
+    if !status.errors.is_empty() {
+        return Err(ApiError::invalid_values(status.errors));
+    }
+    Ok(success("reload", status))
+}
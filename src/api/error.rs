@@ -0,0 +1,178 @@
+use rocket::{
+    http::Status,
+    response::{self, Responder},
+    serde::{
+        json::{self, Json},
+        Serialize,
+    },
+    Request, Response,
+};
+
+use super::Message;
+use crate::animations::get_animations;
+use crate::reload::SettingsError;
+
+/// What went wrong, the same for every route
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The body isn't the JSON the route expects
+    InvalidBody,
+    /// A value of the request isn't allowed
+    InvalidValue,
+    NotFound,
+    /// The request doesn't fit the current state, like confirming a key
+    /// when no calibration is running
+    Conflict,
+    Internal,
+}
+impl ErrorCode {
+    pub fn status(self) -> Status {
+        match self {
+            ErrorCode::InvalidBody => Status::BadRequest,
+            ErrorCode::InvalidValue => Status::UnprocessableEntity,
+            ErrorCode::NotFound => Status::NotFound,
+            ErrorCode::Conflict => Status::Conflict,
+            ErrorCode::Internal => Status::InternalServerError,
+        }
+    }
+    /// Code of the errors answered by Rocket, like unknown routes
+    fn from_status(status: Status) -> ErrorCode {
+        match status.code {
+            404 => ErrorCode::NotFound,
+            409 => ErrorCode::Conflict,
+            422 => ErrorCode::InvalidValue,
+            400..=499 => ErrorCode::InvalidBody,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+/// Error answered by the API, with the HTTP status of its code. Its type is
+/// the resource of the route, like `layers` for `/api/layers/1`.
+#[derive(Serialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct ApiError {
+    #[serde(skip)]
+    status: Status,
+    pub code: ErrorCode,
+    pub message: String,
+    /// Values that would have been accepted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<String>,
+    /// Every problem, when there are several
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+impl ApiError {
+    pub fn new(code: ErrorCode, message: String) -> ApiError {
+        ApiError {
+            status: code.status(),
+            code,
+            message,
+            allowed: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+    pub fn invalid(message: String, allowed: Vec<String>) -> ApiError {
+        ApiError {
+            allowed,
+            ..ApiError::new(ErrorCode::InvalidValue, message)
+        }
+    }
+    /// Several invalid values, like parameters out of their range
+    pub fn invalid_values(errors: Vec<String>) -> ApiError {
+        ApiError {
+            errors: errors.clone(),
+            ..ApiError::new(ErrorCode::InvalidValue, errors.join(", "))
+        }
+    }
+    pub fn not_found(message: String) -> ApiError {
+        ApiError::new(ErrorCode::NotFound, message)
+    }
+    pub fn conflict(message: String) -> ApiError {
+        ApiError::new(ErrorCode::Conflict, message)
+    }
+    pub fn internal(message: String) -> ApiError {
+        ApiError::new(ErrorCode::Internal, message)
+    }
+}
+impl From<SettingsError> for ApiError {
+    fn from(e: SettingsError) -> ApiError {
+        match e {
+            SettingsError::Invalid(errors) => ApiError::invalid_values(errors),
+            SettingsError::Save(e) => ApiError::internal(e),
+        }
+    }
+}
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let r#type = request
+            .uri()
+            .path()
+            .segments()
+            .get(1)
+            .unwrap_or("api")
+            .to_string();
+        let status = self.status;
+        let message = Json(Message {
+            status: "error".to_string(),
+            r#type,
+            data: self,
+        });
+        Response::build_from(message.respond_to(request)?)
+            .status(status)
+            .ok()
+    }
+}
+
+pub type ApiResult<T> = Result<Json<Message<T>>, ApiError>;
+/// JSON body of a request, its errors answered like the others by
+/// `parse_body`
+pub type JsonBody<'r, T> = Result<Json<T>, json::Error<'r>>;
+
+pub fn parse_body<T>(body: JsonBody<T>) -> Result<T, ApiError> {
+    match body {
+        Ok(body) => Ok(body.into_inner()),
+        Err(json::Error::Io(e)) => Err(ApiError::new(
+            ErrorCode::InvalidBody,
+            format!("Couldn't read the body: {}", e),
+        )),
+        Err(json::Error::Parse(_, e)) => Err(ApiError::new(
+            ErrorCode::InvalidBody,
+            format!("Invalid body: {}", e),
+        )),
+    }
+}
+
+/// Checks that an animation exists, listing them otherwise
+pub fn check_animation(animation: &str) -> Result<(), ApiError> {
+    let animations: Vec<String> = get_animations()
+        .iter()
+        .map(|info| info.name.to_string())
+        .collect();
+    if animations.iter().any(|name| name == animation) {
+        Ok(())
+    } else {
+        Err(ApiError::invalid(
+            format!("Unknown animation `{}`", animation),
+            animations,
+        ))
+    }
+}
+
+/// Answers the requests no route could, like unknown routes or bodies over
+/// the size limit
+#[catch(default)]
+pub fn api_catcher(status: Status, request: &Request) -> ApiError {
+    let message = format!(
+        "{}: {} {}",
+        status.reason_lossy(),
+        request.method(),
+        request.uri().path()
+    );
+    ApiError {
+        status,
+        ..ApiError::new(ErrorCode::from_status(status), message)
+    }
+}
@@ -0,0 +1,31 @@
+use rocket::{
+    serde::{json::Json, Serialize},
+    State,
+};
+
+use super::{success, AppState, Message};
+use crate::harmony::functions::{Chord, Key};
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HarmonyState {
+    chord: Option<Chord>,
+    key: Option<Key>,
+    notes: Vec<u8>,
+}
+
+#[get("/harmony")]
+pub async fn get_harmony(state: &State<AppState>) -> Json<Message<HarmonyState>> {
+    let harmony = state
+        .harmony
+        .lock()
+        .expect("Could not take the lock on `harmony`");
+    success(
+        "harmony",
+        HarmonyState {
+            chord: harmony.chord.clone(),
+            key: harmony.key.clone(),
+            notes: harmony.sounding_notes(),
+        },
+    )
+}
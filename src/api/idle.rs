@@ -0,0 +1,69 @@
+use rocket::{
+    serde::{json::Json, Deserialize, Serialize},
+    State,
+};
+
+use super::{
+    error::{check_animation, parse_body, ApiResult, JsonBody},
+    success, AppState, Message,
+};
+use crate::leds::state::LedState;
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct IdleState {
+    /// Seconds, 0 when disabled
+    timeout: u64,
+    animation: String,
+    idle: bool,
+    /// Seconds since the last note
+    since_last_note: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct IdleChanges {
+    timeout: Option<u64>,
+    animation: Option<String>,
+}
+
+fn get_idle_state(leds: &LedState) -> IdleState {
+    let idle = &leds.animator.idle;
+    IdleState {
+        timeout: idle.timeout.as_secs(),
+        animation: idle.animation.to_string(),
+        idle: idle.is_idle(),
+        since_last_note: idle.last_note.elapsed().as_secs(),
+    }
+}
+
+#[get("/idle")]
+pub async fn get_idle(state: &State<AppState>) -> Json<Message<IdleState>> {
+    success("idle", state.leds.run(|leds| get_idle_state(leds)).await)
+}
+/// Saved across restarts
+#[patch("/idle", data = "<changes>")]
+pub async fn set_idle(
+    state: &State<AppState>,
+    changes: JsonBody<'_, IdleChanges>,
+) -> ApiResult<IdleState> {
+    let changes = parse_body(changes)?;
+    if let Some(animation) = &changes.animation {
+        check_animation(animation)?;
+    }
+    {
+        let mut reloader = state.reloader.lock().expect("Couldn't lock the reloader");
+        let mut idle = reloader.config().leds.idle.clone();
+        if let Some(timeout) = changes.timeout {
+            idle.timeout = timeout;
+        }
+        if let Some(animation) = changes.animation {
+            idle.animation = animation;
+        }
+        reloader.update_settings(|settings| settings.idle = Some(idle))?;
+    }
+    Ok(success(
+        "idle",
+        state.leds.run(|leds| get_idle_state(leds)).await,
+    ))
+}
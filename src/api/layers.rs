@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use rocket::{
+    serde::{json::Json, Deserialize},
+    State,
+};
+
+use super::{
+    error::{check_animation, parse_body, ApiError, ApiResult, JsonBody},
+    settings, success, AppState, Message,
+};
+use crate::animations::{
+    compositor::{BlendMode, LayerInfo},
+    parameters::Parameters,
+};
+use crate::leds::state::LedState;
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewLayer {
+    animation: String,
+    opacity: Option<f32>,
+    blend: Option<BlendMode>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct LayerChanges {
+    animation: Option<String>,
+    opacity: Option<f32>,
+    blend: Option<BlendMode>,
+    /// Position in the stack, 0 being the bottom
+    index: Option<usize>,
+}
+
+fn get_layers(leds: &LedState) -> Vec<LayerInfo> {
+    leds.animator
+        .layers
+        .iter()
+        .map(|layer| layer.info())
+        .collect()
+}
+fn unknown_layer(id: usize) -> ApiError {
+    ApiError::not_found(format!("Unknown layer {}", id))
+}
+
+/// Layers from bottom to top
+#[get("/layers")]
+pub async fn list_layers(state: &State<AppState>) -> Json<Message<Vec<LayerInfo>>> {
+    success("layers", state.leds.run(|leds| get_layers(leds)).await)
+}
+#[post("/layers", data = "<layer>")]
+pub async fn add_layer(
+    state: &State<AppState>,
+    layer: JsonBody<'_, NewLayer>,
+) -> ApiResult<Vec<LayerInfo>> {
+    let layer = parse_body(layer)?;
+    check_animation(&layer.animation)?;
+    let layers = state
+        .leds
+        .run(move |leds| {
+            leds.animator
+                .add_layer(
+                    layer.animation,
+                    layer.opacity.unwrap_or(1.0),
+                    layer.blend.unwrap_or(BlendMode::Normal),
+                )
+                .map_err(ApiError::conflict)?;
+            Ok::<_, ApiError>(get_layers(leds))
+        })
+        .await?;
+    Ok(success("layers", layers))
+}
+#[patch("/layers/<id>", data = "<changes>")]
+pub async fn update_layer(
+    state: &State<AppState>,
+    id: usize,
+    changes: JsonBody<'_, LayerChanges>,
+) -> ApiResult<Vec<LayerInfo>> {
+    let changes = parse_body(changes)?;
+    if let Some(animation) = &changes.animation {
+        check_animation(animation)?;
+    }
+    let layers = state
+        .leds
+        .run(move |leds| {
+            let animator = &mut leds.animator;
+            if animator.layer_mut(id).is_none() {
+                return Err(unknown_layer(id));
+            }
+            if let Some(animation) = changes.animation {
+                animator
+                    .set_layer_animation(id, animation)
+                    .map_err(ApiError::conflict)?;
+            }
+            let layer = animator.layer_mut(id).ok_or_else(|| unknown_layer(id))?;
+            if let Some(opacity) = changes.opacity {
+                layer.opacity = opacity.clamp(0.0, 1.0);
+            }
+            if let Some(blend) = changes.blend {
+                layer.blend = blend;
+            }
+            if let Some(index) = changes.index {
+                animator.move_layer(id, index).map_err(ApiError::conflict)?;
+            }
+            Ok(get_layers(leds))
+        })
+        .await?;
+    Ok(success("layers", layers))
+}
+#[delete("/layers/<id>")]
+pub async fn delete_layer(state: &State<AppState>, id: usize) -> ApiResult<Vec<LayerInfo>> {
+    let layers = state
+        .leds
+        .run(move |leds| {
+            if leds.animator.layer_mut(id).is_none() {
+                return Err(unknown_layer(id));
+            }
+            leds.animator.remove_layer(id).map_err(ApiError::conflict)?;
+            Ok(get_layers(leds))
+        })
+        .await?;
+    Ok(success("layers", layers))
+}
+#[get("/layers/<id>/params")]
+pub async fn get_layer_params(state: &State<AppState>, id: usize) -> ApiResult<Parameters> {
+    let parameters = state
+        .leds
+        .run(move |leds| match leds.animator.layer_mut(id) {
+            Some(layer) => Ok(layer.animator.parameters().clone()),
+            None => Err(unknown_layer(id)),
+        })
+        .await?;
+    Ok(success("animation_params", parameters))
+}
+/// Sets several parameters at once, only if all of them are valid. Those of
+/// the primary layer are saved across restarts, as through
+/// `/animation/params`.
+#[patch("/layers/<id>/params", data = "<values>")]
+pub async fn set_layer_params(
+    state: &State<AppState>,
+    id: usize,
+    values: JsonBody<'_, HashMap<String, f32>>,
+) -> ApiResult<Parameters> {
+    let values = parse_body(values)?;
+    let saved = state
+        .leds
+        .run(move |leds| {
+            let layer = leds
+                .animator
+                .layer_mut(id)
+                .ok_or_else(|| unknown_layer(id))?;
+            if id == 0 {
+                // Checked on a copy, the saved settings then apply them
+                let mut parameters = layer.animator.parameters().clone();
+                parameters
+                    .set_all(&values)
+                    .map_err(ApiError::invalid_values)?;
+                return Ok(Some((layer.animation.to_string(), values)));
+            }
+            layer
+                .animator
+                .parameters_mut()
+                .set_all(&values)
+                .map_err(ApiError::invalid_values)?;
+            Ok::<_, ApiError>(None)
+        })
+        .await?;
+    if let Some((animation, values)) = saved {
+        settings::update_settings(state, |settings| {
+            settings
+                .animations
+                .entry(animation)
+                .or_default()
+                .extend(values)
+        })?;
+    }
+    get_layer_params(state, id).await
+}
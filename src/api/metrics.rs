@@ -0,0 +1,16 @@
+use rocket::{serde::json::Json, State};
+
+use super::{success, AppState, Message};
+use crate::leds::scheduler::FrameMetrics;
+
+#[get("/metrics")]
+pub async fn get_metrics(state: &State<AppState>) -> Json<Message<FrameMetrics>> {
+    success(
+        "metrics",
+        state
+            .metrics
+            .lock()
+            .expect("Could not take the lock on `metrics`")
+            .clone(),
+    )
+}
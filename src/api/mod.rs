@@ -1,132 +1,253 @@
+mod calibration;
+mod config;
 mod cors;
+mod error;
+mod harmony;
+mod idle;
+mod layers;
+mod metrics;
+mod recordings;
+mod scenes;
+mod settings;
+mod stats;
+pub mod web;
 
-use crate::structs::{Animator, ColorMode};
-use rocket::serde::{json::Json, Serialize};
-use rocket::{fs::NamedFile, Build, Rocket, State};
+use crate::animations::{
+    get_animations, parameters::Parameters, transition::TransitionKind, AnimationInfo,
+};
+use crate::harmony::Harmony;
+use crate::leds::{
+    scheduler::FrameMetrics,
+    state::{LedHandle, LedState},
+};
+use crate::midi::{recorder::Recorder, sessions::Sessions};
+use crate::reload::Reloader;
+use crate::scenes::SceneStore;
+use crate::stats::StatsStore;
+use crate::structs::{ColorMode, NoteEvent, TransitionConfig, COLOR_MODES};
+use error::{check_animation, parse_body, ApiError, ApiResult, JsonBody};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{Build, Rocket, State};
 use std::{
-    path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    collections::HashMap,
+    sync::{mpsc::Sender, Arc, Mutex},
 };
 
-struct AppState {
-    color_mode: Arc<Mutex<ColorMode>>,
-    animator: Arc<Mutex<Animator>>,
+/// Handles on everything the API can read or change
+pub struct AppState {
+    /// Current config, reloaded when its file changes
+    pub reloader: Arc<Mutex<Reloader>>,
+    /// Animations and color mode, owned by the LED thread
+    pub leds: LedHandle,
+    pub recorder: Arc<Mutex<Recorder>>,
+    pub sessions: Arc<Mutex<Sessions>>,
+    pub stats: Arc<Mutex<StatsStore>>,
+    pub scenes: Arc<Mutex<SceneStore>>,
+    pub harmony: Arc<Mutex<Harmony>>,
+    pub metrics: Arc<Mutex<FrameMetrics>>,
+    pub midi_tx: Sender<NoteEvent>,
 }
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
-struct Message {
+struct Message<T = String> {
     status: String,
     r#type: String,
-    data: String,
+    data: T,
 }
-
-#[post("/color_mode", data = "<mode>")]
-async fn set_color_mode(state: &State<AppState>, mode: String) -> Json<Message> {
-    state
-        .color_mode
-        .lock()
-        .expect("Could not take the lock on `color_mode`")
-        .set_color_mode(mode);
+fn success<T>(r#type: &str, data: T) -> Json<Message<T>> {
     Json(Message {
         status: "success".to_string(),
-        r#type: "color_mode".to_string(),
-        data: state
-            .color_mode
-            .lock()
-            .expect("Could not take the lock on `color_mode`")
-            .mode
-            .to_string(),
+        r#type: r#type.to_string(),
+        data,
     })
 }
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ColorModeBody {
+    /// One of the named modes, or a `#rrggbb` color
+    mode: String,
+}
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct AnimationBody {
+    animation: String,
+}
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct BrightnessBody {
+    brightness: u8,
+}
+
+async fn get_color_mode_body(state: &AppState) -> ColorModeBody {
+    ColorModeBody {
+        mode: state
+            .leds
+            .run(|leds| leds.color_mode.mode.to_string())
+            .await,
+    }
+}
+async fn get_animation_body(state: &AppState) -> AnimationBody {
+    AnimationBody {
+        animation: state
+            .leds
+            .run(|leds| leds.animator.animation().to_string())
+            .await,
+    }
+}
+
+/// Saved across restarts, like the animation and the transition
+#[post("/color_mode", data = "<body>")]
+async fn set_color_mode(
+    state: &State<AppState>,
+    body: JsonBody<'_, ColorModeBody>,
+) -> ApiResult<ColorModeBody> {
+    let mode = parse_body(body)?.mode;
+    ColorMode::validate(&mode).map_err(|e| {
+        let mut allowed: Vec<String> = COLOR_MODES.iter().map(|mode| mode.to_string()).collect();
+        allowed.push("#rrggbb".to_string());
+        ApiError::invalid(e, allowed)
+    })?;
+    settings::update_settings(state, |settings| settings.color_mode = Some(mode))?;
+    Ok(success("color_mode", get_color_mode_body(state).await))
+}
 #[get("/color_mode")]
-async fn get_color_mode(state: &State<AppState>) -> Json<Message> {
-    Json(Message {
-        status: "success".to_string(),
-        r#type: "color_mode".to_string(),
-        data: state
-            .color_mode
-            .lock()
-            .expect("Could not take the lock on `color_mode`")
-            .mode
-            .to_string(),
-    })
+async fn get_color_mode(state: &State<AppState>) -> Json<Message<ColorModeBody>> {
+    success("color_mode", get_color_mode_body(state).await)
 }
-#[post("/animation", data = "<animation>")]
-async fn set_animation(state: &State<AppState>, animation: String) -> Json<Message> {
-    state
-        .animator
-        .lock()
-        .expect("Could not take the lock on `animator`")
-        .set_animation(animation);
-    Json(Message {
-        status: "success".to_string(),
-        r#type: "animation".to_string(),
-        data: state
-            .animator
-            .lock()
-            .expect("Could not take the lock on `animator`")
-            .animation
-            .to_string(),
-    })
+#[post("/animation", data = "<body>")]
+async fn set_animation(
+    state: &State<AppState>,
+    body: JsonBody<'_, AnimationBody>,
+) -> ApiResult<AnimationBody> {
+    let animation = parse_body(body)?.animation;
+    check_animation(&animation)?;
+    settings::update_settings(state, |settings| settings.animation = Some(animation))?;
+    Ok(success("animation", get_animation_body(state).await))
 }
 #[get("/animation")]
-async fn get_animation(state: &State<AppState>) -> Json<Message> {
-    Json(Message {
-        status: "success".to_string(),
-        r#type: "animation".to_string(),
-        data: state
-            .animator
-            .lock()
-            .expect("Could not take the lock on `animator`")
-            .animation
-            .to_string(),
-    })
+async fn get_animation(state: &State<AppState>) -> Json<Message<AnimationBody>> {
+    success("animation", get_animation_body(state).await)
 }
-#[post("/brightness", data = "<brightness>")]
-async fn set_brightness(_state: &State<AppState>, brightness: String) -> Json<Message> {
-    if let Ok(_brightness) = brightness.parse::<u8>() {
-        Json(Message {
-            status: "error".to_string(),
-            r#type: "brightness".to_string(),
-            data: "NOT_IMPLEMENTED".to_string(),
-        })
-    } else {
-        Json(Message {
-            status: "error".to_string(),
-            r#type: "brightness".to_string(),
-            data: "Could not parse brightness".to_string(),
-        })
+/// Name and parameters of the animation set through `/api/animation`. While
+/// idle the ambient animation stands in for it on the primary layer, it
+/// then has the parameters it will come back with.
+fn get_performance_params(leds: &LedState) -> Result<(String, Parameters), ApiError> {
+    let animator = &leds.animator;
+    if let Some(performance) = &animator.idle.performance {
+        return Ok((
+            performance.to_string(),
+            animator.initial_parameters(performance),
+        ));
     }
+    let primary = animator
+        .primary()
+        .ok_or_else(|| ApiError::conflict("No primary layer".to_string()))?;
+    Ok((
+        primary.animation.to_string(),
+        primary.animator.parameters().clone(),
+    ))
+}
+
+#[get("/animation/params")]
+async fn get_animation_params(state: &State<AppState>) -> ApiResult<Parameters> {
+    let (_, parameters) = state.leds.run(|leds| get_performance_params(leds)).await?;
+    Ok(success("animation_params", parameters))
+}
+/// Sets several parameters at once, only if all of them are valid. They
+/// are saved across restarts for the animation of the primary layer.
+#[patch("/animation/params", data = "<values>")]
+async fn set_animation_params(
+    state: &State<AppState>,
+    values: JsonBody<'_, HashMap<String, f32>>,
+) -> ApiResult<Parameters> {
+    let values = parse_body(values)?;
+    // Checked on a copy, the saved settings then apply them
+    let (animation, values) = state
+        .leds
+        .run(move |leds| {
+            let (animation, mut parameters) = get_performance_params(leds)?;
+            parameters
+                .set_all(&values)
+                .map_err(ApiError::invalid_values)?;
+            Ok::<_, ApiError>((animation, values))
+        })
+        .await?;
+    settings::update_settings(state, |settings| {
+        settings
+            .animations
+            .entry(animation)
+            .or_default()
+            .extend(values)
+    })?;
+    get_animation_params(state).await
+}
+#[get("/animations")]
+async fn list_animations() -> Json<Message<Vec<AnimationInfo>>> {
+    success("animations", get_animations())
+}
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TransitionSettings {
+    kind: TransitionKind,
+    /// Milliseconds
+    duration: u64,
+}
+#[get("/transition")]
+async fn get_transition(state: &State<AppState>) -> Json<Message<TransitionSettings>> {
+    success(
+        "transition",
+        state
+            .leds
+            .run(|leds| TransitionSettings {
+                kind: leds.animator.transition,
+                duration: leds.animator.transition_duration.as_millis() as u64,
+            })
+            .await,
+    )
+}
+#[patch("/transition", data = "<body>")]
+async fn set_transition(
+    state: &State<AppState>,
+    body: JsonBody<'_, TransitionSettings>,
+) -> ApiResult<TransitionSettings> {
+    let body = parse_body(body)?;
+    let transition = TransitionConfig {
+        kind: body.kind,
+        duration: body.duration,
+    };
+    settings::update_settings(state, |settings| settings.transition = Some(transition))?;
+    Ok(success("transition", body))
+}
+/// Saved across restarts
+#[post("/brightness", data = "<body>")]
+async fn set_brightness(
+    state: &State<AppState>,
+    body: JsonBody<'_, BrightnessBody>,
+) -> ApiResult<BrightnessBody> {
+    let brightness = parse_body(body)?.brightness;
+    settings::update_settings(state, |settings| settings.brightness = Some(brightness))?;
+    Ok(success("brightness", BrightnessBody { brightness }))
 }
 #[get("/brightness")]
-async fn get_brightness(_state: &State<AppState>) -> Json<Message> {
-    Json(Message {
-        status: "error".to_string(),
-        r#type: "brightness".to_string(),
-        data: "NOT_IMPLEMENTED".to_string(),
-    })
+async fn get_brightness(state: &State<AppState>) -> Json<Message<BrightnessBody>> {
+    let brightness = state
+        .reloader
+        .lock()
+        .expect("Couldn't lock the reloader")
+        .config()
+        .leds
+        .brightness;
+    success("brightness", BrightnessBody { brightness })
 }
 
-#[get("/static/<file..>")]
-async fn files(file: PathBuf) -> NamedFile {
-    NamedFile::open(Path::new("/home/pi/web/build/static").join(file))
-        .await
-        .expect("Could not open file")
-}
-#[get("/")]
-async fn index() -> NamedFile {
-    NamedFile::open("/home/pi/web/build/index.html")
-        .await
-        .expect("Could not open file")
-}
-pub fn main(color_mode: &Arc<Mutex<ColorMode>>, animator: &Arc<Mutex<Animator>>) -> Rocket<Build> {
+pub fn main(state: AppState, web_root: web::WebRoot) -> Rocket<Build> {
     rocket::build()
         .attach(cors::CORS)
-        .manage(AppState {
-            color_mode: color_mode.clone(),
-            animator: animator.clone(),
-        })
-        .mount("/", routes![files, index])
+        .manage(state)
+        .manage(web_root)
+        .mount("/", routes![web::get_web_file])
+        .register("/api", catchers![error::api_catcher])
         .mount(
             "/api",
             routes![
@@ -134,8 +255,48 @@ pub fn main(color_mode: &Arc<Mutex<ColorMode>>, animator: &Arc<Mutex<Animator>>)
                 set_color_mode,
                 get_animation,
                 set_animation,
+                list_animations,
+                get_animation_params,
+                set_animation_params,
+                get_transition,
+                set_transition,
+                settings::get_settings,
+                settings::reset_settings,
+                scenes::list_scenes,
+                scenes::save_scene,
+                scenes::apply_scene,
+                scenes::delete_scene,
+                idle::get_idle,
+                idle::set_idle,
+                config::get_reload,
+                config::reload_config,
+                calibration::get_calibration,
+                calibration::start_calibration,
+                calibration::nudge_calibration,
+                calibration::confirm_calibration,
+                calibration::save_calibration,
+                calibration::cancel_calibration,
+                metrics::get_metrics,
                 get_brightness,
-                set_brightness
+                set_brightness,
+                recordings::get_recording,
+                recordings::start_recording,
+                recordings::stop_recording,
+                recordings::list_recordings,
+                recordings::download_recording,
+                recordings::delete_recording,
+                recordings::play_recording,
+                recordings::list_sessions,
+                recordings::download_session,
+                stats::get_stats,
+                stats::get_stats_sessions,
+                harmony::get_harmony,
+                layers::list_layers,
+                layers::add_layer,
+                layers::update_layer,
+                layers::delete_layer,
+                layers::get_layer_params,
+                layers::set_layer_params
             ],
         )
 }
@@ -0,0 +1,144 @@
+use std::thread;
+
+use paris::{error, info};
+use rocket::{fs::NamedFile, serde::json::Json, State};
+
+use super::{
+    error::{ApiError, ApiResult},
+    success, AppState, Message,
+};
+use crate::midi::{functions::play_midi_file, recorder::Take, sessions::SessionInfo};
+
+fn unknown_recording(name: &str) -> ApiError {
+    ApiError::not_found(format!("Unknown recording `{}`", name))
+}
+
+#[get("/recording")]
+pub async fn get_recording(state: &State<AppState>) -> Json<Message<bool>> {
+    success(
+        "recording",
+        state
+            .recorder
+            .lock()
+            .expect("Could not take the lock on `recorder`")
+            .recording,
+    )
+}
+#[post("/recording/start")]
+pub async fn start_recording(state: &State<AppState>) -> Json<Message<bool>> {
+    let mut recorder = state
+        .recorder
+        .lock()
+        .expect("Could not take the lock on `recorder`");
+    recorder.start();
+    info!("<blue>[Recorder]</> Started recording");
+    success("recording", recorder.recording)
+}
+/// Saves the take, answering its name
+#[post("/recording/stop")]
+pub async fn stop_recording(state: &State<AppState>) -> ApiResult<String> {
+    let result = state
+        .recorder
+        .lock()
+        .expect("Could not take the lock on `recorder`")
+        .stop();
+    match result {
+        Ok(Some(name)) => {
+            info!("<blue>[Recorder]</> Saved take {}", name);
+            Ok(success("recording", name))
+        }
+        Ok(None) => Err(ApiError::conflict("Nothing was recorded".to_string())),
+        Err(e) => {
+            error!("<red>[Recorder]</> Could not save take: {}", e);
+            Err(ApiError::internal(format!("Could not save take: {}", e)))
+        }
+    }
+}
+#[get("/recordings")]
+pub async fn list_recordings(state: &State<AppState>) -> Json<Message<Vec<Take>>> {
+    success(
+        "recordings",
+        state
+            .recorder
+            .lock()
+            .expect("Could not take the lock on `recorder`")
+            .list(),
+    )
+}
+#[get("/recordings/<name>")]
+pub async fn download_recording(
+    state: &State<AppState>,
+    name: &str,
+) -> Result<NamedFile, ApiError> {
+    let path = state
+        .recorder
+        .lock()
+        .expect("Could not take the lock on `recorder`")
+        .path(name)
+        .ok_or_else(|| unknown_recording(name))?;
+    NamedFile::open(path)
+        .await
+        .map_err(|e| ApiError::internal(format!("Could not open recording: {}", e)))
+}
+#[delete("/recordings/<name>")]
+pub async fn delete_recording(state: &State<AppState>, name: &str) -> ApiResult<String> {
+    let result = state
+        .recorder
+        .lock()
+        .expect("Could not take the lock on `recorder`")
+        .delete(name);
+    match result {
+        Ok(true) => Ok(success("recordings", name.to_string())),
+        Ok(false) => Err(unknown_recording(name)),
+        Err(e) => Err(ApiError::internal(format!(
+            "Could not delete recording: {}",
+            e
+        ))),
+    }
+}
+#[post("/recordings/<name>/play")]
+pub async fn play_recording(state: &State<AppState>, name: &str) -> ApiResult<String> {
+    let path = state
+        .recorder
+        .lock()
+        .expect("Could not take the lock on `recorder`")
+        .path(name)
+        .ok_or_else(|| unknown_recording(name))?;
+    let tx = state.midi_tx.clone();
+    let config = state
+        .reloader
+        .lock()
+        .expect("Couldn't lock the reloader")
+        .config()
+        .clone();
+    thread::spawn(move || {
+        info!("<blue>[MIDI]</> Replaying {}", path.display());
+        if let Err(e) = play_midi_file(path.to_string_lossy().to_string(), None, tx, &config) {
+            error!("<red>[MIDI]</> {}", e);
+        }
+    });
+    Ok(success("recordings", name.to_string()))
+}
+#[get("/sessions")]
+pub async fn list_sessions(state: &State<AppState>) -> Json<Message<Vec<SessionInfo>>> {
+    success(
+        "sessions",
+        state
+            .sessions
+            .lock()
+            .expect("Could not take the lock on `sessions`")
+            .list(),
+    )
+}
+#[get("/sessions/<name>")]
+pub async fn download_session(state: &State<AppState>, name: &str) -> Result<NamedFile, ApiError> {
+    let path = state
+        .sessions
+        .lock()
+        .expect("Could not take the lock on `sessions`")
+        .path(name)
+        .ok_or_else(|| ApiError::not_found(format!("Unknown session `{}`", name)))?;
+    NamedFile::open(path)
+        .await
+        .map_err(|e| ApiError::internal(format!("Could not open session: {}", e)))
+}
@@ -0,0 +1,83 @@
+use rocket::{
+    serde::{json::Json, Deserialize},
+    State,
+};
+
+use super::{
+    error::{parse_body, ApiError, ApiResult, JsonBody},
+    success, AppState, Message,
+};
+use crate::scenes::{self, Scene};
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct NewScene {
+    name: String,
+    /// MIDI program, from 0, recalling the scene
+    program: Option<u8>,
+}
+
+#[get("/scenes")]
+pub async fn list_scenes(state: &State<AppState>) -> Json<Message<Vec<Scene>>> {
+    let scenes = state.scenes.lock().expect("Couldn't lock the scenes");
+    success("scenes", scenes.scenes.clone())
+}
+/// Saves the current look of the strip as a scene, replacing the one with
+/// the same name
+#[post("/scenes", data = "<scene>")]
+pub async fn save_scene(
+    state: &State<AppState>,
+    scene: JsonBody<'_, NewScene>,
+) -> ApiResult<Scene> {
+    let NewScene { name, program } = parse_body(scene)?;
+    if name.trim().is_empty() {
+        return Err(ApiError::invalid(
+            "The scene needs a name".to_string(),
+            Vec::new(),
+        ));
+    }
+    if let Some(program) = program {
+        let scenes = state.scenes.lock().expect("Couldn't lock the scenes");
+        if let Some(other) = scenes
+            .by_program(program)
+            .filter(|other| other.name != name)
+        {
+            return Err(ApiError::conflict(format!(
+                "Program {} already recalls scene `{}`",
+                program, other.name
+            )));
+        }
+    }
+    let scene = state
+        .leds
+        .run(move |leds| Scene::capture(name, program, leds))
+        .await;
+    state
+        .scenes
+        .lock()
+        .expect("Couldn't lock the scenes")
+        .insert(scene.clone())
+        .map_err(ApiError::internal)?;
+    Ok(success("scenes", scene))
+}
+#[post("/scenes/<name>/apply")]
+pub async fn apply_scene(state: &State<AppState>, name: &str) -> ApiResult<Scene> {
+    let scene = state
+        .scenes
+        .lock()
+        .expect("Couldn't lock the scenes")
+        .get(name)
+        .cloned()
+        .ok_or_else(|| ApiError::not_found(format!("Unknown scene `{}`", name)))?;
+    scenes::apply_scene(&scene, &state.reloader, &state.leds).map_err(ApiError::conflict)?;
+    Ok(success("scenes", scene))
+}
+#[delete("/scenes/<name>")]
+pub async fn delete_scene(state: &State<AppState>, name: &str) -> ApiResult<Vec<Scene>> {
+    let mut scenes = state.scenes.lock().expect("Couldn't lock the scenes");
+    if scenes.get(name).is_none() {
+        return Err(ApiError::not_found(format!("Unknown scene `{}`", name)));
+    }
+    scenes.remove(name).map_err(ApiError::internal)?;
+    Ok(success("scenes", scenes.scenes.clone()))
+}
@@ -0,0 +1,42 @@
+use rocket::{serde::json::Json, State};
+
+use super::{
+    error::{ApiError, ApiResult},
+    success, AppState, Message,
+};
+use crate::reload::SettingsError;
+use crate::settings::Settings;
+
+/// Changes the settings restored at startup, and applies them
+pub fn update_settings<F>(state: &AppState, change: F) -> Result<(), SettingsError>
+where
+    F: FnOnce(&mut Settings),
+{
+    state
+        .reloader
+        .lock()
+        .expect("Couldn't lock the reloader")
+        .update_settings(change)
+}
+
+/// Settings changed through the API, applied over the config file
+#[get("/settings")]
+pub async fn get_settings(state: &State<AppState>) -> Json<Message<Settings>> {
+    success(
+        "settings",
+        state
+            .reloader
+            .lock()
+            .expect("Couldn't lock the reloader")
+            .settings()
+            .clone(),
+    )
+}
+/// Goes back to the config file, forgetting the settings changed through the
+/// API
+#[delete("/settings")]
+pub async fn reset_settings(state: &State<AppState>) -> ApiResult<Settings> {
+    let mut reloader = state.reloader.lock().expect("Couldn't lock the reloader");
+    reloader.reset_settings().map_err(ApiError::internal)?;
+    Ok(success("settings", reloader.settings().clone()))
+}
@@ -0,0 +1,39 @@
+use rocket::{serde::json::Json, State};
+
+use super::{success, AppState, Message};
+use crate::midi::recorder::unix_time;
+use crate::stats::{
+    functions::{get_report, StatsReport},
+    SessionStats,
+};
+
+#[get("/stats?<from>&<to>")]
+pub async fn get_stats(
+    state: &State<AppState>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Json<Message<StatsReport>> {
+    let from = from.unwrap_or(0);
+    let to = to.unwrap_or_else(unix_time);
+    let sessions = state
+        .stats
+        .lock()
+        .expect("Could not take the lock on `stats`")
+        .sessions_between(from, to);
+    success("stats", get_report(&sessions, from, to))
+}
+#[get("/stats/sessions?<from>&<to>")]
+pub async fn get_stats_sessions(
+    state: &State<AppState>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Json<Message<Vec<SessionStats>>> {
+    success(
+        "stats",
+        state
+            .stats
+            .lock()
+            .expect("Could not take the lock on `stats`")
+            .sessions_between(from.unwrap_or(0), to.unwrap_or_else(unix_time)),
+    )
+}
@@ -0,0 +1,126 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use paris::{info, warn};
+use rocket::{
+    fs::NamedFile,
+    http::{ContentType, Header},
+    response::{self, Responder},
+    Request, Response, State,
+};
+
+use crate::structs::ApiConfig;
+
+const INDEX: &str = "index.html";
+/// The files of the build under `static/` have a hash in their name, they
+/// never change
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// The other ones, like the index, are checked for changes on every load
+const REVALIDATE: &str = "no-cache";
+
+#[cfg(feature = "embed-web")]
+static WEB_BUILD: include_dir::Dir = include_dir::include_dir!("$CARGO_MANIFEST_DIR/web/build");
+
+#[cfg(feature = "embed-web")]
+fn get_embedded_file(path: &Path) -> Option<&'static [u8]> {
+    WEB_BUILD.get_file(path).map(|file| file.contents())
+}
+#[cfg(not(feature = "embed-web"))]
+fn get_embedded_file(_path: &Path) -> Option<&'static [u8]> {
+    None
+}
+
+/// Where the files of the web UI are served from
+pub enum WebRoot {
+    /// The build of `web/`, like `/home/pi/web/build`
+    Directory(PathBuf),
+    /// The build of `web/` embedded at compile time, with the `embed-web`
+    /// feature
+    Embedded,
+}
+impl WebRoot {
+    pub fn new(config: &ApiConfig) -> WebRoot {
+        if cfg!(feature = "embed-web") {
+            info!("<blue>[API]</> Serving the embedded web UI");
+            return WebRoot::Embedded;
+        }
+        let root = PathBuf::from(&config.web_root);
+        if !root.join(INDEX).is_file() {
+            warn!(
+                "<yellow>[API]</> No web UI in {}, build `web/` there",
+                root.display()
+            );
+        }
+        WebRoot::Directory(root)
+    }
+    async fn open(&self, path: &Path) -> Option<WebFile> {
+        let cache_control = if path.starts_with("static") {
+            IMMUTABLE
+        } else {
+            REVALIDATE
+        };
+        let body = match self {
+            WebRoot::Directory(root) => {
+                let path = root.join(path);
+                // Directories open fine, but can't be read
+                if !path.is_file() {
+                    return None;
+                }
+                WebBody::File(NamedFile::open(path).await.ok()?)
+            }
+            WebRoot::Embedded => {
+                let content_type = path
+                    .extension()
+                    .and_then(|extension| ContentType::from_extension(&extension.to_string_lossy()))
+                    .unwrap_or(ContentType::Binary);
+                WebBody::Embedded(get_embedded_file(path)?, content_type)
+            }
+        };
+        Some(WebFile {
+            body,
+            cache_control,
+        })
+    }
+}
+
+enum WebBody {
+    File(NamedFile),
+    Embedded(&'static [u8], ContentType),
+}
+
+/// A file of the web UI, with its caching headers
+pub struct WebFile {
+    body: WebBody,
+    cache_control: &'static str,
+}
+impl<'r> Responder<'r, 'static> for WebFile {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = match self.body {
+            WebBody::File(file) => file.respond_to(request)?,
+            WebBody::Embedded(contents, content_type) => Response::build()
+                .header(content_type)
+                .sized_body(contents.len(), Cursor::new(contents))
+                .finalize(),
+        };
+        response.set_header(Header::new("Cache-Control", self.cache_control));
+        Ok(response)
+    }
+}
+
+/// Serves the files of the web UI. The paths without an extension are
+/// routes of the UI, they get its index for it to route them.
+#[get("/<path..>", rank = 20)]
+pub async fn get_web_file(web_root: &State<WebRoot>, path: PathBuf) -> Option<WebFile> {
+    // Unknown API routes stay errors
+    if path.starts_with("api") {
+        return None;
+    }
+    if path.as_os_str().is_empty() {
+        return web_root.open(Path::new(INDEX)).await;
+    }
+    match web_root.open(&path).await {
+        Some(file) => Some(file),
+        None if path.extension().is_none() => web_root.open(Path::new(INDEX)).await,
+        None => None,
+    }
+}
@@ -0,0 +1,217 @@
+use std::env;
+use std::io;
+use std::process;
+
+use argh::FromArgs;
+use paris::{error, info, success, warn};
+use portmidi::PortMidi;
+use rocket::tokio::task;
+
+use crate::config::{env_overrides, load_config};
+use crate::leds::{
+    calibration::{save_key_map, Calibration},
+    state::LedHandle,
+};
+use crate::settings::load_settings;
+use crate::structs::{Config, Paths, CONFIG_PATH};
+
+/// Environment variable giving the config file when `--config` is not used
+const CONFIG_ENV: &str = "PIANO_VISUALIZER_CONFIG";
+
+/// Lights a LED strip along a MIDI keyboard as it is played.
+#[derive(FromArgs)]
+pub struct Args {
+    /// config file, $PIANO_VISUALIZER_CONFIG or `config.toml` by default.
+    /// Its keys can be overridden by environment variables,
+    /// `PIANO_VISUALIZER__LEDS__PIN=18` setting `leds.pin`.
+    #[argh(option, short = 'c')]
+    config: Option<String>,
+    /// only check the config file, like `config --check`
+    #[argh(switch)]
+    pub check_config: bool,
+    #[argh(subcommand)]
+    pub command: Option<Subcommand>,
+}
+impl Args {
+    pub fn paths(&self) -> Paths {
+        let config = self
+            .config
+            .clone()
+            .or_else(|| env::var(CONFIG_ENV).ok())
+            .unwrap_or_else(|| CONFIG_PATH.to_string());
+        Paths::new(&config)
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Subcommand {
+    Run(RunArgs),
+    Devices(DevicesArgs),
+    Play(PlayArgs),
+    Calibrate(CalibrateArgs),
+    Config(ConfigArgs),
+}
+
+/// Start the visualizer and its API, what runs without a subcommand
+#[derive(FromArgs, Default)]
+#[argh(subcommand, name = "run")]
+pub struct RunArgs {
+    /// print the strip to the terminal instead of driving it
+    #[argh(switch)]
+    pub simulate: bool,
+}
+
+/// List the MIDI devices, to find the `midi.id` of the keyboard
+#[derive(FromArgs)]
+#[argh(subcommand, name = "devices")]
+pub struct DevicesArgs {}
+
+/// Play a MIDI file to the strip
+#[derive(FromArgs)]
+#[argh(subcommand, name = "play")]
+pub struct PlayArgs {
+    /// MIDI file
+    #[argh(positional)]
+    pub file: String,
+    /// print the strip to the terminal instead of driving it
+    #[argh(switch)]
+    pub simulate: bool,
+}
+
+/// Map the keys to the LEDs from the terminal, without the API
+#[derive(FromArgs)]
+#[argh(subcommand, name = "calibrate")]
+pub struct CalibrateArgs {
+    /// print the strip to the terminal instead of driving it
+    #[argh(switch)]
+    pub simulate: bool,
+}
+
+/// Print the effective config: the file, its environment overrides and the
+/// settings saved through the API
+#[derive(FromArgs)]
+#[argh(subcommand, name = "config")]
+pub struct ConfigArgs {
+    /// only check the config file, exiting with 1 if it is invalid
+    #[argh(switch)]
+    pub check: bool,
+}
+
+/// Reads the config file, exiting on its problems
+pub fn load_config_or_exit(paths: &Paths) -> Config {
+    load_config(&paths.config).unwrap_or_else(|errors| {
+        for e in errors.iter() {
+            error!("<red>[Config]</> {}: {}", paths.config, e);
+        }
+        process::exit(1)
+    })
+}
+
+pub fn list_devices() {
+    let midi_context = PortMidi::new().expect("Couldn't create PortMidi context");
+    let devices = midi_context
+        .devices()
+        .expect("Couldn't list the MIDI devices");
+    if devices.is_empty() {
+        warn!("<yellow>[MIDI]</> No device found");
+    }
+    for device in devices {
+        println!("{}", device);
+    }
+}
+
+pub fn print_config(paths: &Paths, check: bool) {
+    let file = load_config_or_exit(paths);
+    if check {
+        success!("<green>[Config]</> {} is valid", paths.config);
+        return;
+    }
+    for (path, value) in env_overrides() {
+        println!("# `{}` set to `{}` by the environment", path, value);
+    }
+    let config = load_settings(&paths.settings, &file).apply(&file);
+    let config = toml::Value::try_from(&config).expect("The config should serialize");
+    print!(
+        "{}",
+        toml::to_string(&config).expect("The config should serialize")
+    );
+}
+
+const CALIBRATION_HELP: &str = "Press the lowest key of the keyboard, then:
+  <number>  moves its LED by this many LEDs, negative towards the start of the strip
+  c         confirms it, then press the highest key and any key in between
+  s         saves the mapping to the config file and quits
+  q         quits without saving";
+
+/// Runs the calibration wizard from commands typed in the terminal, the keys
+/// being pressed on the keyboard
+pub async fn calibrate(leds: &LedHandle, paths: &Paths) {
+    leds.run(|leds| leds.calibration = Some(Calibration::new(&leds.config)))
+        .await;
+    println!("{}", CALIBRATION_HELP);
+    loop {
+        // Read on a blocking thread, the LED commands being awaited on the
+        // same runtime
+        let line = task::spawn_blocking(|| {
+            let mut line = String::new();
+            let read = io::stdin()
+                .read_line(&mut line)
+                .expect("Couldn't read the terminal");
+            (read > 0).then_some(line)
+        })
+        .await
+        .expect("Couldn't read the terminal");
+        let Some(line) = line else {
+            return;
+        };
+        let command = line.trim().to_string();
+        let result = match command.as_str() {
+            "" => Ok(()),
+            "q" => return,
+            "s" => {
+                let key_map = leds
+                    .run(|leds| {
+                        leds.calibration
+                            .as_ref()
+                            .expect("The calibration is running")
+                            .key_map()
+                    })
+                    .await;
+                match key_map.and_then(|key_map| save_key_map(&paths.config, &key_map)) {
+                    Ok(()) => {
+                        success!("<green>[Calibration]</> Saved to {}", paths.config);
+                        return;
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            _ => {
+                leds.run(move |leds| {
+                    let calibration = leds
+                        .calibration
+                        .as_mut()
+                        .expect("The calibration is running");
+                    let (action, point) = match command.as_str() {
+                        "c" => ("Confirmed", calibration.confirm()?),
+                        _ => {
+                            let offset = command
+                                .parse()
+                                .map_err(|_| format!("Unknown command `{}`", command))?;
+                            ("Moved", calibration.nudge(offset)?)
+                        }
+                    };
+                    info!(
+                        "<blue>[Calibration]</> {} note {} at LED {}",
+                        action, point.note, point.led
+                    );
+                    Ok(())
+                })
+                .await
+            }
+        };
+        if let Err(e) = result {
+            warn!("<yellow>[Calibration]</> {}", e);
+        }
+    }
+}
@@ -0,0 +1,534 @@
+use std::env;
+use std::fmt;
+use std::fs;
+
+use paris::warn;
+
+use crate::animations::{get_animations, get_parameter_schema};
+use crate::leds::mapping::{KeyMap, LEGACY_DENSITY};
+use crate::structs::{ColorMode, Config};
+
+/// GPIO pins that can drive a strip: PWM, PCM and SPI
+const LED_PINS: [i32; 12] = [10, 12, 13, 18, 19, 21, 31, 40, 41, 45, 52, 53];
+const MAX_FPS: u32 = 240;
+/// Prefix of the environment variables overriding the keys of the config
+/// file, `PIANO_VISUALIZER__LEDS__NUM_LEDS=144` setting `leds.num_leds`
+const ENV_PREFIX: &str = "PIANO_VISUALIZER__";
+
+/// A problem of the config file
+#[derive(Debug)]
+pub struct ConfigError {
+    /// Key the problem is about, like `leds.mapping.keys`, empty for the
+    /// whole file
+    pub path: String,
+    /// Line of the key, from 1, when it could be found
+    pub line: Option<usize>,
+    pub message: String,
+}
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "`{}`: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Collects the problems of a config file, locating their key in it
+struct Problems<'a> {
+    source: &'a str,
+    errors: Vec<ConfigError>,
+}
+impl Problems<'_> {
+    fn add(&mut self, path: &str, message: String) {
+        self.errors.push(ConfigError {
+            path: path.to_string(),
+            line: find_line(self.source, path),
+            message,
+        });
+    }
+    fn check(&mut self, path: &str, valid: bool, message: &str) {
+        if !valid {
+            self.add(path, message.to_string());
+        }
+    }
+}
+
+/// Line of a key or a table of a config file, from 1. Only finds the keys
+/// under a table header, not the dotted keys and inline tables.
+fn find_line(source: &str, path: &str) -> Option<usize> {
+    let mut table = String::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            table = line
+                .trim_matches(|c| c == '[' || c == ']')
+                .trim()
+                .to_string();
+            if table == path {
+                return Some(index + 1);
+            }
+        } else if let Some((key, _)) = line.split_once('=') {
+            let key = key.trim().trim_matches('"');
+            if format!("{}.{}", table, key) == path {
+                return Some(index + 1);
+            }
+        }
+    }
+    None
+}
+
+/// Keys of `value` that are missing from `known`, with their path
+fn find_unknown_keys(value: &toml::Value, known: &toml::Value, path: &str) -> Vec<String> {
+    let (Some(table), Some(known)) = (value.as_table(), known.as_table()) else {
+        return Vec::new();
+    };
+    let mut unknown = Vec::new();
+    for (key, value) in table.iter() {
+        let key_path = if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        match known.get(key) {
+            Some(known) => unknown.extend(find_unknown_keys(value, known, &key_path)),
+            None => unknown.push(key_path),
+        }
+    }
+    unknown
+}
+
+/// Keys whose value differs between two configs, the tables deeper than
+/// `depth` being compared as a whole
+fn find_changed_keys(
+    old: &toml::Value,
+    new: &toml::Value,
+    path: &str,
+    depth: usize,
+) -> Vec<String> {
+    match (old.as_table(), new.as_table()) {
+        (Some(old), Some(new)) if depth > 0 => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter()
+                .flat_map(|key| {
+                    let key_path = if path.is_empty() {
+                        key.to_string()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    match (old.get(key), new.get(key)) {
+                        (Some(old), Some(new)) => find_changed_keys(old, new, &key_path, depth - 1),
+                        _ => vec![key_path],
+                    }
+                })
+                .collect()
+        }
+        _ if old != new => vec![path.to_string()],
+        _ => Vec::new(),
+    }
+}
+/// Keys whose value differs between two configs, down to the parameters of
+/// each animation
+pub fn diff_configs(old: &Config, new: &Config) -> Vec<String> {
+    let old = toml::Value::try_from(old).expect("The config should serialize");
+    let new = toml::Value::try_from(new).expect("The config should serialize");
+    find_changed_keys(&old, &new, "", 3)
+}
+
+/// Keys of the config overridden by environment variables, with their value
+pub fn env_overrides() -> Vec<(String, String)> {
+    let mut overrides: Vec<(String, String)> = env::vars()
+        .filter_map(|(name, value)| {
+            let path = name.strip_prefix(ENV_PREFIX)?;
+            Some((path.to_lowercase().replace("__", "."), value))
+        })
+        .collect();
+    overrides.sort();
+    overrides
+}
+/// Sets a key of the config, read as a TOML value or else as a string
+fn apply_override(config: &mut toml::Value, path: &str, value: &str) {
+    let value = toml::from_str::<toml::Value>(&format!("value = {}", value))
+        .ok()
+        .and_then(|table| table.get("value").cloned())
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let last = keys.pop().expect("Split always gives a key");
+    let mut table = config;
+    for key in keys {
+        let Some(parent) = table.as_table_mut() else {
+            return;
+        };
+        table = parent
+            .entry(key.to_string())
+            .or_insert_with(|| toml::Value::Table(toml::value::Table::new()));
+    }
+    if let Some(table) = table.as_table_mut() {
+        table.insert(last.to_string(), value);
+    }
+}
+
+/// Translates the `leds.offsets` and `leds.shift` of the configs older than
+/// `[leds.mapping]` into one, every key overridden with the LED it used to
+/// light. Returns whether there were such keys.
+fn migrate_legacy_mapping(value: &mut toml::Value) -> Result<bool, ConfigError> {
+    let Some(leds) = value.get_mut("leds").and_then(|leds| leds.as_table_mut()) else {
+        return Ok(false);
+    };
+    let (offsets, shift) = (leds.remove("offsets"), leds.remove("shift"));
+    if offsets.is_none() && shift.is_none() {
+        return Ok(false);
+    }
+    // A saved calibration wins over the legacy keys
+    if leds.contains_key("mapping") {
+        return Ok(true);
+    }
+    let error = |path: &str, message: String| ConfigError {
+        path: path.to_string(),
+        line: None,
+        message,
+    };
+    let offsets = match offsets {
+        Some(offsets) => offsets
+            .as_array()
+            .and_then(|offsets| {
+                offsets
+                    .iter()
+                    .map(|pair| match pair.as_array().map(|pair| pair.as_slice()) {
+                        Some([note, offset]) => Some((note.as_integer()?, offset.as_integer()?)),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<(i64, i64)>>>()
+            })
+            .ok_or_else(|| {
+                error(
+                    "leds.offsets",
+                    "Must be pairs of a note and an offset, like [[92, 2], [55, 1]]".to_string(),
+                )
+            })?,
+        None => Vec::new(),
+    };
+    let shift = match shift {
+        Some(shift) => shift
+            .as_integer()
+            .ok_or_else(|| error("leds.shift", "Must be an integer".to_string()))?,
+        None => 0,
+    };
+    // Without it, the missing key is reported when reading the config
+    let Some(num_leds) = leds
+        .get("num_leds")
+        .and_then(|num_leds| num_leds.as_integer())
+    else {
+        return Ok(true);
+    };
+
+    let mut overrides = toml::value::Table::new();
+    for note in 21..=108 {
+        let offset = offsets
+            .iter()
+            .find(|(from, _)| note > *from)
+            .map_or(0, |(_, offset)| *offset);
+        let led = num_leds - (2 * (note - 20) - (offset - shift));
+        if !(0..num_leds).contains(&led) {
+            return Err(error(
+                "leds.offsets",
+                format!(
+                    "Note {} is mapped to LED {}, beyond the {} LEDs of the strip",
+                    note, led, num_leds
+                ),
+            ));
+        }
+        overrides.insert(
+            note.to_string(),
+            toml::Value::Array(vec![toml::Value::Integer(led)]),
+        );
+    }
+    let mut mapping = toml::value::Table::new();
+    mapping.insert("keys".to_string(), toml::Value::Integer(88));
+    mapping.insert("first_note".to_string(), toml::Value::Integer(21));
+    mapping.insert(
+        "density".to_string(),
+        toml::Value::Float(LEGACY_DENSITY as f64),
+    );
+    mapping.insert("reversed".to_string(), toml::Value::Boolean(true));
+    mapping.insert("overrides".to_string(), toml::Value::Table(overrides));
+    leds.insert("mapping".to_string(), toml::Value::Table(mapping));
+    Ok(true)
+}
+
+/// Reads the config file, applies the environment overrides and checks it,
+/// reporting every problem found
+pub fn load_config(path: &str) -> Result<Config, Vec<ConfigError>> {
+    let source = fs::read_to_string(path).map_err(|e| {
+        vec![ConfigError {
+            path: String::new(),
+            line: None,
+            message: format!("Couldn't read {}: {}", path, e),
+        }]
+    })?;
+    parse_config(&source, &env_overrides())
+}
+
+fn parse_config(source: &str, overrides: &[(String, String)]) -> Result<Config, Vec<ConfigError>> {
+    // Syntax errors and wrong types stop the parsing, only the first one is
+    // known. Its message already tells the key and the line.
+    let to_error = |e: toml::de::Error| {
+        vec![ConfigError {
+            path: String::new(),
+            line: None,
+            message: e.to_string(),
+        }]
+    };
+    let mut value: toml::Value = toml::from_str(source).map_err(to_error)?;
+    let migrated = migrate_legacy_mapping(&mut value).map_err(|mut e| {
+        e.line = find_line(source, &e.path);
+        vec![e]
+    })?;
+    if migrated {
+        warn!(
+            "<yellow>[Config]</> `leds.offsets` and `leds.shift` are deprecated, remove them and map \
+             the keys with `[leds.mapping]` or `piano_visualizer calibrate`"
+        );
+    }
+    let config: Config = if overrides.is_empty() && !migrated {
+        // Keeps the line of the wrong types in the errors
+        toml::from_str(source).map_err(to_error)?
+    } else {
+        for (path, override_value) in overrides {
+            apply_override(&mut value, path, override_value);
+        }
+        value.clone().try_into().map_err(to_error)?
+    };
+
+    let mut problems = Problems {
+        source,
+        errors: Vec::new(),
+    };
+    // What serde ignored is what it doesn't write back
+    let known = toml::Value::try_from(&config).expect("The config should serialize");
+    for key in find_unknown_keys(&value, &known, "") {
+        problems.add(&key, "Unknown key".to_string());
+    }
+    validate(&config, &mut problems);
+    for e in problems.errors.iter_mut() {
+        if overrides.iter().any(|(path, _)| *path == e.path) {
+            e.line = None;
+            e.message += ", as set by the environment";
+        }
+    }
+
+    if problems.errors.is_empty() {
+        Ok(config)
+    } else {
+        // In the order of the file, the keys that couldn't be found last
+        problems
+            .errors
+            .sort_by_key(|e| e.line.unwrap_or(usize::MAX));
+        Err(problems.errors)
+    }
+}
+
+/// Checks a config that wasn't read as is from a file, like one with the
+/// saved settings applied
+pub fn check_config(config: &Config) -> Vec<ConfigError> {
+    let mut problems = Problems {
+        source: "",
+        errors: Vec::new(),
+    };
+    validate(config, &mut problems);
+    problems.errors
+}
+
+fn validate(config: &Config, problems: &mut Problems) {
+    let leds = &config.leds;
+    problems.check(
+        "leds.pin",
+        LED_PINS.contains(&leds.pin),
+        "This GPIO pin can't drive a strip, use a PWM pin like 18",
+    );
+    problems.check("leds.channel", leds.channel <= 1, "Must be 0 or 1");
+    problems.check(
+        "leds.brightness",
+        leds.brightness > 0,
+        "Must be above 0 for the strip to light",
+    );
+    problems.check("leds.num_leds", leds.num_leds > 0, "Must be above 0");
+    problems.check(
+        "leds.fps",
+        (1..=MAX_FPS).contains(&leds.fps),
+        &format!("Must be between 1 and {}", MAX_FPS),
+    );
+    if let Err(e) = ColorMode::validate(&leds.color_mode) {
+        problems.add("leds.color_mode", e);
+    }
+
+    let animations = get_animations();
+    let is_animation = |name: &str| animations.iter().any(|info| info.name == name);
+    for (path, animation) in [
+        ("leds.animation", &leds.animation),
+        ("leds.idle.animation", &leds.idle.animation),
+    ] {
+        problems.check(
+            path,
+            is_animation(animation),
+            &format!("Unknown animation `{}`", animation),
+        );
+    }
+    for (animation, values) in leds.animations.iter() {
+        let path = format!("leds.animations.{}", animation);
+        let Some(schema) = get_parameter_schema(animation) else {
+            problems.add(&path, format!("Unknown animation `{}`", animation));
+            continue;
+        };
+        for (name, value) in values.iter() {
+            let path = format!("{}.{}", path, name);
+            match schema.iter().find(|schema| schema.name == name) {
+                Some(schema) => {
+                    if let Err(e) = schema.validate(*value) {
+                        problems.add(&path, e);
+                    }
+                }
+                None => problems.add(&path, format!("`{}` has no such parameter", animation)),
+            }
+        }
+    }
+    if leds.num_leds > 0 {
+        if let Err(e) = KeyMap::new(&leds.mapping, leds.num_leds) {
+            problems.add("leds.mapping", e);
+        }
+    }
+
+    problems.check(
+        "midi.buffer_size",
+        config.midi.buffer_size > 0,
+        "Must be above 0",
+    );
+    problems.check(
+        "midi.max_keys_processing",
+        config.midi.max_keys_processing > 0,
+        "Must be above 0",
+    );
+    problems.check(
+        "harmony.window",
+        config.harmony.window > 0,
+        "Must be above 0",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = include_str!("../config.toml");
+
+    fn replace(from: &str, to: &str) -> String {
+        assert!(CONFIG.contains(from), "`{}` isn't in config.toml", from);
+        CONFIG.replacen(from, to, 1)
+    }
+    fn line_of(source: &str, text: &str) -> usize {
+        source
+            .lines()
+            .position(|line| line.contains(text))
+            .expect("The text should be in the config")
+            + 1
+    }
+    fn errors(source: &str, overrides: &[(&str, &str)]) -> Vec<ConfigError> {
+        let overrides: Vec<(String, String)> = overrides
+            .iter()
+            .map(|(path, value)| (path.to_string(), value.to_string()))
+            .collect();
+        parse_config(source, &overrides).expect_err("The config should be invalid")
+    }
+
+    #[test]
+    fn finds_keys_and_tables() {
+        let source = "[leds]\npin = 18\n\n[leds.mapping]\n\"keys\" = 88\n";
+        assert_eq!(find_line(source, "leds"), Some(1));
+        assert_eq!(find_line(source, "leds.pin"), Some(2));
+        assert_eq!(find_line(source, "leds.mapping"), Some(4));
+        assert_eq!(find_line(source, "leds.mapping.keys"), Some(5));
+        assert_eq!(find_line(source, "leds.keys"), None);
+    }
+
+    #[test]
+    fn finds_unknown_keys_in_nested_tables() {
+        let value: toml::Value = toml::from_str("a = 1\nb = 2\n[c]\nd = 3\ne = 4").unwrap();
+        let known: toml::Value = toml::from_str("a = 0\n[c]\nd = 0").unwrap();
+        assert_eq!(find_unknown_keys(&value, &known, ""), vec!["b", "c.e"]);
+    }
+
+    #[test]
+    fn overrides_keys_with_toml_values_or_strings() {
+        let mut value: toml::Value = toml::from_str("[leds]\nnum_leds = 176").unwrap();
+        apply_override(&mut value, "leds.num_leds", "144");
+        apply_override(&mut value, "leds.color_mode", "rainbow");
+        apply_override(&mut value, "midi.device.name", "\"Piano\"");
+        assert_eq!(value["leds"]["num_leds"].as_integer(), Some(144));
+        assert_eq!(value["leds"]["color_mode"].as_str(), Some("rainbow"));
+        assert_eq!(value["midi"]["device"]["name"].as_str(), Some("Piano"));
+    }
+
+    #[test]
+    fn accepts_the_default_config() {
+        let config = parse_config(CONFIG, &[]).expect("config.toml should be valid");
+        assert_eq!(config.leds.num_leds, 176);
+    }
+
+    #[test]
+    fn reports_unknown_keys_with_their_line() {
+        let source = replace("fps = 60\n", "fps = 60\nwhat = 1\n");
+        let errors = errors(&source, &[]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "leds.what");
+        assert_eq!(errors[0].line, Some(line_of(&source, "what = 1")));
+        assert_eq!(errors[0].message, "Unknown key");
+    }
+
+    #[test]
+    fn reports_every_invalid_value() {
+        let source = replace("color_mode = \"#7300ff\"", "color_mode = \"plaid\"");
+        let source = source.replacen("fps = 60", "fps = 0", 1);
+        let errors = errors(&source, &[]);
+        let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["leds.color_mode", "leds.fps"]);
+        assert_eq!(errors[0].line, Some(line_of(&source, "plaid")));
+        assert!(errors[0].message.starts_with("Unknown color mode `plaid`"));
+    }
+
+    #[test]
+    fn applies_the_environment_overrides() {
+        let config = parse_config(CONFIG, &[("leds.fps".to_string(), "30".to_string())])
+            .expect("The override is valid");
+        assert_eq!(config.leds.fps, 30);
+    }
+
+    #[test]
+    fn marks_the_errors_of_the_environment_overrides() {
+        let errors = errors(CONFIG, &[("leds.fps", "0")]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "leds.fps");
+        assert_eq!(errors[0].line, None);
+        assert!(errors[0].message.ends_with(", as set by the environment"));
+    }
+
+    #[test]
+    fn reads_the_legacy_offsets_as_a_mapping() {
+        let source = replace(
+            "fps = 60\n",
+            "fps = 60\noffsets = [[92, 2], [55, 1]]\nshift = 0\n",
+        );
+        let start = source.find("[leds.mapping]").unwrap();
+        let end = source.find("[leds.idle]").unwrap();
+        let source = format!("{}{}", &source[..start], &source[end..]);
+        let config = parse_config(&source, &[]).expect("The legacy keys should be read");
+        let key_map = KeyMap::new(&config.leds.mapping, config.leds.num_leds).unwrap();
+        assert_eq!(key_map.position(21), Some(174));
+        assert_eq!(key_map.position(60), Some(97));
+        assert_eq!(key_map.position(108), Some(2));
+    }
+}
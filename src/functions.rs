@@ -1,3 +1,9 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+
 pub fn hex_to_rgb(hex: &String) -> [u8; 3] {
     let mut rgb = [0; 3];
     let hex = hex.as_str().strip_prefix("#").unwrap_or("000000");
@@ -6,3 +12,29 @@ pub fn hex_to_rgb(hex: &String) -> [u8; 3] {
     }
     rgb
 }
+
+/// Writes to a temporary file first, then renames it over `path`, so a crash
+/// never leaves it truncated and readers never see it half-written
+pub fn write_atomically(path: &Path, data: &str) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+/// Reads a JSON store, empty when it doesn't exist yet. One that can't be
+/// read is moved aside to `<path>.bad` rather than being overwritten by the
+/// next save, the error telling where.
+pub fn load_json_store<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    let error = match fs::read_to_string(path) {
+        Ok(data) => match serde_json::from_str(&data) {
+            Ok(store) => return Ok(store),
+            Err(e) => format!("Couldn't parse {}: {}", path.display(), e),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => format!("Couldn't read {}: {}", path.display(), e),
+    };
+    let bad = PathBuf::from(format!("{}.bad", path.display()));
+    match fs::rename(path, &bad) {
+        Ok(()) => Err(format!("{}, moved it to {}", error, bad.display())),
+        Err(e) => Err(format!("{}, and couldn't move it aside: {}", error, e)),
+    }
+}
@@ -0,0 +1,236 @@
+use rocket::serde::Serialize;
+
+pub const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Krumhansl-Kessler key profiles, starting from the tonic
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+const MAJOR_SCALE: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MINOR_SCALE: [u8; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+/// Chord qualities with their intervals from the root, richest first so that
+/// a seventh chord wins over the triad it contains.
+const CHORD_TEMPLATES: [(&str, &[u8]); 11] = [
+    ("dominant7", &[0, 4, 7, 10]),
+    ("major7", &[0, 4, 7, 11]),
+    ("minor7", &[0, 3, 7, 10]),
+    ("half-diminished7", &[0, 3, 6, 10]),
+    ("diminished7", &[0, 3, 6, 9]),
+    ("major", &[0, 4, 7]),
+    ("minor", &[0, 3, 7]),
+    ("diminished", &[0, 3, 6]),
+    ("augmented", &[0, 4, 8]),
+    ("sus4", &[0, 5, 7]),
+    ("sus2", &[0, 2, 7]),
+];
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Chord {
+    /// Pitch class of the root, 0 being C
+    pub root: u8,
+    pub name: String,
+    pub quality: String,
+    /// 0 for root position, 1 for first inversion, ...
+    pub inversion: u8,
+    /// Pitch classes of the chord tones, root first
+    pub tones: Vec<u8>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde")]
+pub struct Key {
+    /// Pitch class of the tonic, 0 being C
+    pub tonic: u8,
+    pub name: String,
+    pub minor: bool,
+    /// Correlation with the key profile, between -1 and 1
+    pub confidence: f32,
+}
+impl Key {
+    /// Scale degree of a pitch class (0 for the tonic), `None` if it isn't
+    /// diatonic to the key.
+    pub fn degree(&self, pitch_class: u8) -> Option<usize> {
+        let scale = if self.minor { MINOR_SCALE } else { MAJOR_SCALE };
+        let interval = (pitch_class + 12 - self.tonic) % 12;
+        scale.iter().position(|step| *step == interval)
+    }
+}
+
+/// Identifies the chord formed by the given notes. Needs at least three
+/// distinct pitch classes.
+pub fn detect_chord(notes: &[u8]) -> Option<Chord> {
+    let bass = *notes.iter().min()? % 12;
+    let mut pitch_classes: Vec<u8> = notes.iter().map(|note| note % 12).collect();
+    pitch_classes.sort_unstable();
+    pitch_classes.dedup();
+    if pitch_classes.len() < 3 {
+        return None;
+    }
+    let mut best: Option<(usize, bool, usize, u8)> = None;
+    for (template_index, (_, intervals)) in CHORD_TEMPLATES.iter().enumerate() {
+        for root in pitch_classes.iter() {
+            let contains_all = intervals
+                .iter()
+                .all(|interval| pitch_classes.contains(&((root + interval) % 12)));
+            if !contains_all {
+                continue;
+            }
+            // Prefer the template covering the most notes, then the root in the bass
+            let candidate = (intervals.len(), *root == bass, template_index, *root);
+            let better = match best {
+                None => true,
+                Some((size, in_bass, _, _)) => (candidate.0, candidate.1) > (size, in_bass),
+            };
+            if better {
+                best = Some(candidate);
+            }
+        }
+    }
+    let (_, _, template_index, root) = best?;
+    let (quality, intervals) = CHORD_TEMPLATES[template_index];
+    let tones: Vec<u8> = intervals
+        .iter()
+        .map(|interval| (root + interval) % 12)
+        .collect();
+    Some(Chord {
+        root,
+        name: format!("{} {}", NOTE_NAMES[root as usize], quality),
+        quality: quality.to_string(),
+        inversion: tones.iter().position(|tone| *tone == bass).unwrap_or(0) as u8,
+        tones,
+    })
+}
+
+fn correlation(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / 12.0;
+    let mean_b = b.iter().sum::<f32>() / 12.0;
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for i in 0..12 {
+        covariance += (a[i] - mean_a) * (b[i] - mean_b);
+        variance_a += (a[i] - mean_a).powi(2);
+        variance_b += (b[i] - mean_b).powi(2);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return 0.0;
+    }
+    covariance / (variance_a * variance_b).sqrt()
+}
+
+/// Estimates the key from a pitch class histogram with the Krumhansl-Schmuckler
+/// algorithm.
+pub fn detect_key(histogram: &[f32; 12]) -> Option<Key> {
+    if histogram.iter().all(|weight| *weight == 0.0) {
+        return None;
+    }
+    let mut best: Option<Key> = None;
+    for tonic in 0..12u8 {
+        for (minor, profile) in [(false, MAJOR_PROFILE), (true, MINOR_PROFILE)] {
+            let mut rotated = [0.0; 12];
+            for (i, weight) in profile.iter().enumerate() {
+                rotated[(i + tonic as usize) % 12] = *weight;
+            }
+            let confidence = correlation(histogram, &rotated);
+            if best.as_ref().is_none_or(|key| confidence > key.confidence) {
+                best = Some(Key {
+                    tonic,
+                    name: format!(
+                        "{} {}",
+                        NOTE_NAMES[tonic as usize],
+                        if minor { "minor" } else { "major" }
+                    ),
+                    minor,
+                    confidence,
+                });
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Histogram of a C major scale, each note played once
+    const C_MAJOR_SCALE: [f32; 12] = [1.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0];
+
+    #[test]
+    fn detects_a_major_triad() {
+        let chord = detect_chord(&[60, 64, 67]).unwrap();
+        assert_eq!(chord.name, "C major");
+        assert_eq!(chord.root, 0);
+        assert_eq!(chord.inversion, 0);
+        assert_eq!(chord.tones, vec![0, 4, 7]);
+    }
+
+    #[test]
+    fn detects_a_minor_triad() {
+        let chord = detect_chord(&[57, 60, 64]).unwrap();
+        assert_eq!(chord.name, "A minor");
+        assert_eq!(chord.quality, "minor");
+        assert_eq!(chord.tones, vec![9, 0, 4]);
+    }
+
+    #[test]
+    fn detects_the_inversion_from_the_bass() {
+        let chord = detect_chord(&[64, 67, 72]).unwrap();
+        assert_eq!(chord.name, "C major");
+        assert_eq!(chord.inversion, 1);
+        let chord = detect_chord(&[43, 60, 64]).unwrap();
+        assert_eq!(chord.inversion, 2);
+    }
+
+    #[test]
+    fn prefers_the_seventh_chord_over_its_triad() {
+        let chord = detect_chord(&[55, 59, 62, 65]).unwrap();
+        assert_eq!(chord.name, "G dominant7");
+    }
+
+    #[test]
+    fn needs_three_pitch_classes_for_a_chord() {
+        assert_eq!(detect_chord(&[]), None);
+        assert_eq!(detect_chord(&[60, 64]), None);
+        assert_eq!(detect_chord(&[60, 72, 84]), None);
+        assert_eq!(detect_chord(&[60, 61, 62]), None);
+    }
+
+    #[test]
+    fn detects_c_major_from_its_scale() {
+        let key = detect_key(&C_MAJOR_SCALE).unwrap();
+        assert_eq!(key.name, "C major");
+        assert!(!key.minor);
+        assert!(key.confidence > 0.5);
+    }
+
+    #[test]
+    fn detects_a_minor_from_its_tonic_chord() {
+        let mut histogram = [0.0; 12];
+        for (pitch_class, weight) in [(9, 4.0), (0, 2.0), (4, 3.0), (11, 1.0), (2, 1.0)] {
+            histogram[pitch_class] = weight;
+        }
+        let key = detect_key(&histogram).unwrap();
+        assert_eq!(key.name, "A minor");
+    }
+
+    #[test]
+    fn detects_no_key_without_notes() {
+        assert_eq!(detect_key(&[0.0; 12]), None);
+    }
+
+    #[test]
+    fn gives_the_degrees_of_the_key() {
+        let key = detect_key(&C_MAJOR_SCALE).unwrap();
+        assert_eq!(key.degree(0), Some(0));
+        assert_eq!(key.degree(7), Some(4));
+        assert_eq!(key.degree(1), None);
+    }
+}
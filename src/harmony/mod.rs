@@ -0,0 +1,130 @@
+pub mod functions;
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use cichlid::HSV;
+use portmidi::MidiEvent;
+
+use crate::midi::functions::get_midi_event_type;
+use crate::structs::{Config, MidiEventType};
+use functions::{detect_chord, detect_key, Chord, Key};
+
+const SUSTAIN_PEDAL: u8 = 64;
+
+/// Follows the note stream to know which chord is currently sounding and
+/// which key is being played in over a sliding window.
+#[derive(Debug)]
+pub struct Harmony {
+    pub window: Duration,
+    pub chord: Option<Chord>,
+    pub key: Option<Key>,
+    held: Vec<bool>,
+    sustained: Vec<bool>,
+    sustain: bool,
+    history: VecDeque<(Instant, u8, u8)>,
+}
+impl Harmony {
+    pub fn new(config: &Config) -> Harmony {
+        Harmony {
+            window: Duration::from_secs(config.harmony.window),
+            chord: None,
+            key: None,
+            held: vec![false; 128],
+            sustained: vec![false; 128],
+            sustain: false,
+            history: VecDeque::new(),
+        }
+    }
+    pub fn handle_event(&mut self, event: &MidiEvent) {
+        let note = (event.message.data1 & 0x7f) as usize;
+        match get_midi_event_type(event.message.status, event.message.data2) {
+            MidiEventType::NoteOn => {
+                self.held[note] = true;
+                self.sustained[note] = false;
+                self.history
+                    .push_back((Instant::now(), note as u8, event.message.data2));
+            }
+            MidiEventType::NoteOff => {
+                self.held[note] = false;
+                self.sustained[note] = self.sustain;
+            }
+            MidiEventType::ControlChange => {
+                if event.message.status & 0xf0 != 0xb0 || event.message.data1 != SUSTAIN_PEDAL {
+                    return;
+                }
+                self.sustain = event.message.data2 >= 64;
+                if !self.sustain {
+                    self.sustained
+                        .iter_mut()
+                        .for_each(|sustained| *sustained = false);
+                }
+            }
+            MidiEventType::ProgramChange => return,
+        }
+        self.analyze();
+    }
+    /// Notes held down or kept ringing by the sustain pedal
+    pub fn sounding_notes(&self) -> Vec<u8> {
+        (0..128)
+            .filter(|note| self.held[*note] || self.sustained[*note])
+            .map(|note| note as u8)
+            .collect()
+    }
+    fn analyze(&mut self) {
+        self.chord = detect_chord(&self.sounding_notes());
+
+        while let Some((time, _, _)) = self.history.front() {
+            if time.elapsed() <= self.window {
+                break;
+            }
+            self.history.pop_front();
+        }
+        let mut histogram = [0.0; 12];
+        for (_, note, velocity) in self.history.iter() {
+            histogram[(note % 12) as usize] += *velocity as f32;
+        }
+        self.key = detect_key(&histogram);
+    }
+    /// Chord and key detected so far
+    pub fn snapshot(&self) -> HarmonySnapshot {
+        HarmonySnapshot {
+            chord: self.chord.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+/// Chord and key detected at some point, kept by the LED thread to color
+/// the notes without waiting on the `Harmony`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HarmonySnapshot {
+    pub chord: Option<Chord>,
+    pub key: Option<Key>,
+}
+impl HarmonySnapshot {
+    /// Colors a note by its function: the hue follows its degree in the
+    /// detected key, chord tones are lit fully and other notes dimmed.
+    /// Without a key, the hue follows the circle of fifths.
+    pub fn get_color(&self, note: u8) -> [u8; 4] {
+        let pitch_class = note % 12;
+        let in_chord = self
+            .chord
+            .as_ref()
+            .is_some_and(|chord| chord.tones.contains(&pitch_class));
+        let value = if in_chord || self.chord.is_none() {
+            255
+        } else {
+            100
+        };
+        let hue = match &self.key {
+            Some(key) => match key.degree(pitch_class) {
+                Some(degree) => (degree * 256 / 7) as u8,
+                None => return [value / 3, value / 3, value / 3, 0],
+            },
+            None => ((pitch_class as usize * 7 % 12) * 256 / 12) as u8,
+        };
+        let rgb = HSV::new(hue, 255, value).to_rgb_rainbow();
+        [rgb.b, rgb.g, rgb.r, 0]
+    }
+}
@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::Path;
+
+use rocket::serde::Serialize;
+use rs_ws281x::RawColor;
+use toml_edit::{value, Array, DocumentMut, InlineTable, Item, Table};
+
+use super::mapping::{get_key_center, KeyMap, LEGACY_DENSITY};
+use crate::functions::write_atomically;
+use crate::structs::LedsConfig;
+
+const CURRENT_COLOR: RawColor = [255, 255, 255, 0];
+const CONFIRMED_COLOR: RawColor = [0, 60, 0, 0];
+
+/// What the wizard waits for
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum CalibrationStep {
+    /// The lowest key of the keyboard
+    Lowest,
+    /// The highest key of the keyboard
+    Highest,
+    /// Any key in between, to correct the mapping where the strip stretched,
+    /// until the mapping is saved
+    Reference,
+}
+
+/// A key and the LED facing its center
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(crate = "rocket::serde")]
+pub struct CalibrationPoint {
+    pub note: u8,
+    pub led: usize,
+}
+
+/// Maps the keys to the LEDs from a few keys whose LED the user found: the
+/// wizard lights the LED a pressed key is mapped to, the user nudges it until
+/// it faces the key and confirms it. The keys in between are interpolated.
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    num_leds: usize,
+    black_key_brightness: f32,
+    pub lowest: Option<CalibrationPoint>,
+    pub highest: Option<CalibrationPoint>,
+    pub references: Vec<CalibrationPoint>,
+    /// Key pressed last, not confirmed yet
+    pub current: Option<CalibrationPoint>,
+}
+impl Calibration {
+    pub fn new(config: &LedsConfig) -> Calibration {
+        Calibration {
+            num_leds: config.num_leds,
+            black_key_brightness: config.mapping.black_key_brightness,
+            lowest: None,
+            highest: None,
+            references: Vec::new(),
+            current: None,
+        }
+    }
+    pub fn step(&self) -> CalibrationStep {
+        match (self.lowest, self.highest) {
+            (None, _) => CalibrationStep::Lowest,
+            (Some(_), None) => CalibrationStep::Highest,
+            (Some(_), Some(_)) => CalibrationStep::Reference,
+        }
+    }
+    /// Confirmed keys, from the lowest one
+    pub fn points(&self) -> Vec<CalibrationPoint> {
+        let mut points: Vec<CalibrationPoint> = self
+            .lowest
+            .iter()
+            .chain(self.references.iter())
+            .chain(self.highest.iter())
+            .copied()
+            .collect();
+        points.sort_by_key(|point| point.note);
+        points
+    }
+    /// Selects the key to calibrate, starting from the LED it is mapped to.
+    /// Notes out of the current mapping start from the closest mapped key.
+    pub fn press(&mut self, note: u8, key_map: &KeyMap) {
+        let confirmed = self.points().into_iter().find(|point| point.note == note);
+        let last_note = key_map.first_note + key_map.keys.len() as u8 - 1;
+        let led = match confirmed {
+            Some(point) => point.led,
+            None => key_map
+                .position(note.clamp(key_map.first_note, last_note))
+                .unwrap_or(0),
+        };
+        self.current = Some(CalibrationPoint { note, led });
+    }
+    /// Moves the LED of the selected key, towards the end of the strip for a
+    /// positive `leds`
+    pub fn nudge(&mut self, leds: i64) -> Result<CalibrationPoint, String> {
+        let current = self
+            .current
+            .as_mut()
+            .ok_or_else(|| "Press a key first".to_string())?;
+        current.led = (current.led as i64 + leds).clamp(0, self.num_leds as i64 - 1) as usize;
+        Ok(*current)
+    }
+    /// Records the LED of the selected key, and moves on to the next step
+    pub fn confirm(&mut self) -> Result<CalibrationPoint, String> {
+        let current = self
+            .current
+            .ok_or_else(|| "Press a key first".to_string())?;
+        match (self.lowest, self.highest) {
+            (None, _) => self.lowest = Some(current),
+            (Some(lowest), None) => {
+                if current.note <= lowest.note {
+                    return Err(format!(
+                        "The highest key must be above the lowest one, note {}",
+                        lowest.note
+                    ));
+                }
+                self.highest = Some(current);
+            }
+            (Some(lowest), Some(highest)) => {
+                if current.note == lowest.note {
+                    self.lowest = Some(current);
+                } else if current.note == highest.note {
+                    self.highest = Some(current);
+                } else if current.note > lowest.note && current.note < highest.note {
+                    self.references.retain(|point| point.note != current.note);
+                    self.references.push(current);
+                } else {
+                    return Err(format!(
+                        "Note {} is not on the keyboard, from note {} to {}",
+                        current.note, lowest.note, highest.note
+                    ));
+                }
+            }
+        }
+        self.current = None;
+        Ok(current)
+    }
+    /// Interpolates the LEDs of every key between the confirmed ones
+    pub fn key_map(&self) -> Result<KeyMap, String> {
+        let (Some(lowest), Some(highest)) = (self.lowest, self.highest) else {
+            return Err("The lowest and highest keys must be confirmed first".to_string());
+        };
+        // Position of the center of each confirmed key, on the keyboard and
+        // along the strip
+        let points: Vec<(f32, f32)> = self
+            .points()
+            .iter()
+            .map(|point| (get_key_center(point.note), point.led as f32 + 0.5))
+            .collect();
+        // Linear between two confirmed keys, the outermost segments extended
+        // to the edges of the keyboard
+        let to_strip = |position: f32| {
+            let segment = points
+                .windows(2)
+                .position(|segment| position < segment[1].0)
+                .unwrap_or(points.len() - 2);
+            let ((from, from_led), (to, to_led)) = (points[segment], points[segment + 1]);
+            from_led + (position - from) * (to_led - from_led) / (to - from)
+        };
+        KeyMap::from_positions(
+            lowest.note,
+            highest.note - lowest.note + 1,
+            self.num_leds,
+            self.black_key_brightness,
+            to_strip,
+        )
+    }
+    /// Lights the LED of the selected key, and dimly the confirmed ones
+    pub fn draw(&self, leds: &mut [RawColor]) {
+        leds.fill([0, 0, 0, 0]);
+        for point in self.points() {
+            if let Some(led) = leds.get_mut(point.led) {
+                *led = CONFIRMED_COLOR;
+            }
+        }
+        if let Some(current) = self.current {
+            if let Some(led) = leds.get_mut(current.led) {
+                *led = CURRENT_COLOR;
+            }
+        }
+    }
+}
+
+/// Writes the LEDs of every key and the span measured along the strip to
+/// the `[leds.mapping]` section of the config file, as overrides. The rest of
+/// the file is kept as it was written, comments included.
+pub fn save_key_map(path: &str, key_map: &KeyMap) -> Result<(), String> {
+    let file = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+    let mut document: DocumentMut = file
+        .parse()
+        .map_err(|e| format!("Couldn't parse {}: {}", path, e))?;
+    let leds = document
+        .get_mut("leds")
+        .and_then(|leds| leds.as_table_mut())
+        .ok_or_else(|| format!("No [leds] section in {}", path))?;
+    // The calibration replaces the legacy mapping, every key being overridden
+    let (offsets, shift) = (leds.remove("offsets"), leds.remove("shift"));
+    if (offsets.is_some() || shift.is_some()) && !leds.contains_key("mapping") {
+        let mut mapping = Table::new();
+        mapping["density"] = value(LEGACY_DENSITY as f64);
+        mapping["reversed"] = value(true);
+        leds["mapping"] = Item::Table(mapping);
+    }
+    let mapping = leds
+        .get_mut("mapping")
+        .and_then(|mapping| mapping.as_table_mut())
+        .ok_or_else(|| format!("No [leds.mapping] section in {}", path))?;
+    mapping["keys"] = value(key_map.keys.len() as i64);
+    mapping["first_note"] = value(key_map.first_note as i64);
+
+    // The existing section keeps its place in the file
+    if !mapping
+        .get("overrides")
+        .is_some_and(|overrides| overrides.is_table())
+    {
+        mapping["overrides"] = Item::Table(Table::new());
+    }
+    let overrides = mapping["overrides"]
+        .as_table_mut()
+        .expect("The overrides were just made a table");
+    overrides.clear();
+    // Rounded, the LEDs being far wider than a thousandth
+    let round = |position: f32| (position as f64 * 1000.0).round() / 1000.0;
+    for key in key_map.keys.iter() {
+        let mut key_override = InlineTable::new();
+        let leds: Array = key.leds.iter().map(|led| *led as i64).collect();
+        key_override.insert("leds", leds.into());
+        key_override.insert("start", round(key.start).into());
+        key_override.insert("end", round(key.end).into());
+        overrides[&key.note.to_string()] = value(key_override);
+    }
+
+    write_atomically(Path::new(path), &document.to_string())
+        .map_err(|e| format!("Couldn't write {}: {}", path, e))
+}
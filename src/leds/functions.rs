@@ -1,76 +1,89 @@
-use std::sync::{
-    mpsc::{Receiver, TryRecvError},
-    Arc, Mutex,
-};
+use std::sync::mpsc::{Receiver, TryRecvError};
 
-use paris::error;
-use portmidi::MidiEvent;
-use rs_ws281x::Controller;
+use paris::{error, success};
+use rs_ws281x::{ChannelBuilder, Controller, ControllerBuilder, StripType};
 
-use crate::structs::{Animator, ColorMode, MidiEventType};
+use super::state::{Command, LedState};
+use super::strip::Strip;
+use crate::structs::{LedsConfig, MidiEventType, NoteEvent, NoteTime};
 
-pub fn get_note_position(note: u8, config: &crate::structs::Config) -> usize {
-    if (note < 20) || (note > 108) {
-        return 0;
-    }
-    let mut note_offset = 0;
-    for i in 0..config.leds.offsets.len() {
-        if note > config.leds.offsets[i][0] {
-            note_offset = config.leds.offsets[i][1];
-            break;
-        }
-    }
-    note_offset -= config.leds.shift;
-    let note_pos_raw = 2 * (note - 20) - note_offset;
-    config.leds.num_leds - (note_pos_raw as usize)
+/// Opens the strip
+pub fn create_controller(config: &LedsConfig) -> Result<Controller, String> {
+    let controller = ControllerBuilder::new()
+        .freq(800_000)
+        .dma(10)
+        .channel(
+            config.channel,
+            ChannelBuilder::new()
+                .pin(config.pin)
+                .count(config.num_leds as i32)
+                .strip_type(StripType::Ws2812)
+                .brightness(config.brightness)
+                .build(),
+        )
+        .build()
+        .map_err(|e| {
+            format!(
+                "Couldn't create the controller on pin {}: {}",
+                config.pin, e
+            )
+        })?;
+    success!(
+        "<green>[WS2812]</> Created controller on pin {}",
+        config.pin
+    );
+    Ok(controller)
 }
+
+/// Applies the queued commands and every note received since the previous
+/// frame, then renders the next one. Returns the times the applied notes
+/// happened at.
 pub fn animate_strip(
-    animator: &Arc<Mutex<Animator>>,
-    controller: &mut Controller,
-    midi_rx: &Receiver<(MidiEventType, MidiEvent, usize)>,
-    color_mode: &Arc<Mutex<ColorMode>>,
-) {
-    match midi_rx.try_recv() {
-        Ok(msg) => {
-            let (event_type, _event, led_index) = msg;
-            match event_type {
-                MidiEventType::NoteOn => animator
-                    .lock()
-                    .expect("Couldn't lock the animator")
-                    .note_on(
-                        led_index,
-                        color_mode
-                            .lock()
-                            .expect("Couldn't lock the color_mode")
-                            .get_color(led_index),
-                    ),
-                MidiEventType::NoteOff => animator
-                    .lock()
-                    .expect("Couldn't lock the animator")
-                    .note_off(
-                        led_index,
-                        color_mode
-                            .lock()
-                            .expect("Couldn't lock the color_mode")
-                            .get_color(led_index),
-                    ),
-                _ => {}
+    state: &mut LedState,
+    strip: &mut Strip,
+    commands: &Receiver<Command>,
+    midi_rx: &Receiver<NoteEvent>,
+    delta: f32,
+) -> Vec<NoteTime> {
+    state.apply_commands(commands);
+
+    let mut received = Vec::new();
+    loop {
+        let note = match midi_rx.try_recv() {
+            Ok(note) => note,
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                error!("<red>[WS2812]</> MIDI Channel disconnected");
+                return received;
             }
+        };
+        if let Some(calibration) = state.calibration.as_mut() {
+            if matches!(note.event_type, MidiEventType::NoteOn) {
+                calibration.press(note.event.message.data1, &state.key_map);
+            }
+            continue;
         }
-        Err(TryRecvError::Disconnected) => {
-            error!("<red>[WS2812]</> MIDI Channel disconnected");
-            return;
+        // Notes out of the keyboard have no LED
+        let Some(key) = state.key_map.key(note.event.message.data1) else {
+            continue;
+        };
+        let color = state
+            .color_mode
+            .get_color(key.center(), note.event.message.data1);
+        match note.event_type {
+            MidiEventType::NoteOn => state.animator.note_on(key, color, note.event.message.data2),
+            MidiEventType::NoteOff => state.animator.note_off(key, color),
+            _ => continue,
         }
-        _ => {}
+        received.push(note.time);
     }
-
-    animator
-        .lock()
-        .expect("Couldn't lock the animator")
-        .update();
-    animator
-        .lock()
-        .expect("Couldn't lock the animator")
-        .draw(controller);
-    controller.render().expect("Couldn't render");
+    match &state.calibration {
+        Some(calibration) => calibration.draw(strip.leds_mut(state.config.channel)),
+        None => {
+            state.animator.update(delta);
+            state.animator.draw(strip.leds_mut(state.config.channel));
+        }
+    }
+    strip.render();
+    received
 }
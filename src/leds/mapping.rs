@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Width of a white key, in millimeters: an octave is 164.5 mm wide
+const WHITE_KEY_WIDTH: f32 = 23.5;
+const BLACK_KEY_WIDTH: f32 = 13.7;
+/// Index of the white key of each note of an octave, starting from C, black
+/// keys taking the one on their left
+const WHITE_INDEX: [u8; 12] = [0, 0, 1, 1, 2, 3, 3, 4, 4, 5, 5, 6];
+const BLACK_KEYS: [u8; 5] = [1, 3, 6, 8, 10];
+/// Density of the strips of the configs older than `[leds.mapping]`, which
+/// lit 2 LEDs per semitone
+pub const LEGACY_DENSITY: f32 = 144.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MappingConfig {
+    /// Number of keys of the keyboard, usually 25, 49, 61, 76 or 88
+    pub keys: u8,
+    /// MIDI note of the lowest key
+    pub first_note: u8,
+    /// LEDs per meter of the strip
+    pub density: f32,
+    /// LED facing the left edge of the lowest key, can be fractional
+    #[serde(default)]
+    pub offset: f32,
+    /// The strip starts at the highest key
+    #[serde(default)]
+    pub reversed: bool,
+    /// LEDs of some keys, by MIDI note, replacing the computed ones
+    #[serde(default)]
+    pub overrides: HashMap<String, KeyOverride>,
+    /// Brightness of the black keys relative to the white ones, below 1 to
+    /// tell them apart
+    #[serde(default = "default_black_key_brightness")]
+    pub black_key_brightness: f32,
+}
+fn default_black_key_brightness() -> f32 {
+    1.0
+}
+
+/// LEDs of a key replacing the computed ones
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum KeyOverride {
+    /// The key spanning from the first to the last of them
+    Leds(Vec<usize>),
+    /// The key spanning from `start` to `end` along the strip, as measured
+    /// by the calibration
+    Span {
+        leds: Vec<usize>,
+        start: f32,
+        end: f32,
+    },
+}
+impl KeyOverride {
+    pub fn leds(&self) -> &[usize] {
+        match self {
+            KeyOverride::Leds(leds) | KeyOverride::Span { leds, .. } => leds,
+        }
+    }
+}
+
+pub fn is_black_key(note: u8) -> bool {
+    BLACK_KEYS.contains(&(note % 12))
+}
+/// Position of the center of a key from the left edge of the lowest C of
+/// the MIDI range, in millimeters. Black keys are centered between the
+/// white keys around them.
+pub fn get_key_center(note: u8) -> f32 {
+    let white_index = (note / 12) as f32 * 7.0 + WHITE_INDEX[(note % 12) as usize] as f32;
+    if is_black_key(note) {
+        (white_index + 1.0) * WHITE_KEY_WIDTH
+    } else {
+        (white_index + 0.5) * WHITE_KEY_WIDTH
+    }
+}
+pub fn get_key_width(note: u8) -> f32 {
+    if is_black_key(note) {
+        BLACK_KEY_WIDTH
+    } else {
+        WHITE_KEY_WIDTH
+    }
+}
+/// LEDs whose center is in front of a key, the one facing the center of the
+/// key first, and the edges of the key along the strip. The LEDs may be off
+/// the strip.
+fn get_key_leds(note: u8, to_strip: &impl Fn(f32) -> f32) -> (Vec<i64>, f32, f32) {
+    let center = get_key_center(note);
+    let half_width = get_key_width(note) / 2.0;
+    let center_led = to_strip(center).floor() as i64;
+    let (from, to) = (to_strip(center - half_width), to_strip(center + half_width));
+    let (from, to) = (from.min(to), from.max(to));
+    let mut leds = vec![center_led];
+    leds.extend(
+        ((from - 0.5).ceil() as i64..(to - 0.5).ceil() as i64).filter(|led| *led != center_led),
+    );
+    (leds, from, to)
+}
+
+/// Part of the strip facing a key
+#[derive(Debug, Clone)]
+pub struct Key {
+    pub note: u8,
+    /// LEDs whose center faces the key, the one facing its center first
+    pub leds: Vec<usize>,
+    /// Edges of the key along the strip, LED `i` spanning from `i` to `i + 1`
+    pub start: f32,
+    pub end: f32,
+    /// Relative brightness of the key, lower for the black keys when they
+    /// are told apart
+    pub brightness: f32,
+}
+impl Key {
+    fn new(note: u8, leds: Vec<usize>, start: f32, end: f32, black_key_brightness: f32) -> Key {
+        Key {
+            note,
+            leds,
+            start,
+            end,
+            brightness: if is_black_key(note) {
+                black_key_brightness
+            } else {
+                1.0
+            },
+        }
+    }
+    /// LED facing the center of the key
+    pub fn center(&self) -> usize {
+        self.leds[0]
+    }
+    /// LEDs the key covers, even partly
+    pub fn span(&self) -> Range<usize> {
+        self.start.max(0.0).floor() as usize..self.end.max(0.0).ceil() as usize
+    }
+    /// Part of a LED covered by the key, between 0 and 1, to anti-alias its
+    /// edges
+    pub fn coverage(&self, led: usize) -> f32 {
+        let (from, to) = (led as f32, led as f32 + 1.0);
+        (self.end.min(to) - self.start.max(from)).max(0.0)
+    }
+}
+
+/// LEDs facing each key of the keyboard
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    pub first_note: u8,
+    /// Keys of the keyboard, from the lowest one
+    pub keys: Vec<Key>,
+}
+impl KeyMap {
+    /// Computes the LEDs of every key from the size of the keys and the
+    /// density of the strip, then applies the overrides
+    pub fn new(config: &MappingConfig, num_leds: usize) -> Result<KeyMap, String> {
+        if config.keys == 0 || config.first_note as usize + config.keys as usize > 128 {
+            return Err(format!(
+                "A keyboard of {} keys can't start at note {}",
+                config.keys, config.first_note
+            ));
+        }
+        if !config.density.is_finite() || config.density <= 0.0 {
+            return Err(format!("Invalid LED density {}", config.density));
+        }
+        if !(0.0..=1.0).contains(&config.black_key_brightness) {
+            return Err(format!(
+                "Invalid black key brightness {}, it must be between 0 and 1",
+                config.black_key_brightness
+            ));
+        }
+        let mut overrides = HashMap::new();
+        for (note, key_override) in config.overrides.iter() {
+            let note: u8 = note
+                .parse()
+                .map_err(|_| format!("Invalid note `{}` in the overrides", note))?;
+            overrides.insert(note, key_override);
+        }
+
+        let leds_per_mm = config.density / 1000.0;
+        let first_note = config.first_note;
+        let left_edge = get_key_center(first_note) - get_key_width(first_note) / 2.0;
+        // Position along the strip of a position of the keyboard, in LEDs,
+        // LED `i` spanning from `i` to `i + 1`
+        let to_strip = |position: f32| {
+            let led = config.offset + (position - left_edge) * leds_per_mm;
+            if config.reversed {
+                num_leds as f32 - led
+            } else {
+                led
+            }
+        };
+
+        let mut key_map = KeyMap {
+            first_note,
+            keys: Vec::new(),
+        };
+        for note in first_note..first_note + config.keys {
+            let (leds, start, end) = get_key_leds(note, &to_strip);
+            // The computed LEDs of the overridden keys may be off the strip
+            if !overrides.contains_key(&note) {
+                if let Some(led) = leds
+                    .iter()
+                    .find(|led| **led < 0 || **led >= num_leds as i64)
+                {
+                    return Err(format!(
+                        "Note {} is mapped to LED {}, beyond the {} LEDs of the strip",
+                        note, led, num_leds
+                    ));
+                }
+            }
+            let leds = leds.into_iter().map(|led| led.max(0) as usize).collect();
+            key_map.keys.push(Key::new(
+                note,
+                leds,
+                start,
+                end,
+                config.black_key_brightness,
+            ));
+        }
+        for (note, key_override) in overrides {
+            key_map.set_key(note, key_override, num_leds)?;
+        }
+        Ok(key_map)
+    }
+    /// Maps each key to the LEDs in front of it, `to_strip` giving the
+    /// position along the strip of a position of the keyboard, as in `new`.
+    /// The strip may not reach the outer edges of the outermost keys, their
+    /// LEDs off the strip are left out.
+    pub fn from_positions(
+        first_note: u8,
+        keys: u8,
+        num_leds: usize,
+        black_key_brightness: f32,
+        to_strip: impl Fn(f32) -> f32,
+    ) -> Result<KeyMap, String> {
+        let mut key_map = KeyMap {
+            first_note,
+            keys: Vec::new(),
+        };
+        for note in first_note..first_note + keys {
+            let (leds, start, end) = get_key_leds(note, &to_strip);
+            if leds[0] < 0 || leds[0] >= num_leds as i64 {
+                return Err(format!(
+                    "Note {} is mapped to LED {}, beyond the {} LEDs of the strip",
+                    note, leds[0], num_leds
+                ));
+            }
+            let leds = leds
+                .into_iter()
+                .filter(|led| *led >= 0 && *led < num_leds as i64)
+                .map(|led| led as usize)
+                .collect();
+            key_map.keys.push(Key::new(
+                note,
+                leds,
+                start.max(0.0),
+                end.min(num_leds as f32),
+                black_key_brightness,
+            ));
+        }
+        Ok(key_map)
+    }
+    /// Replaces the LEDs of a key
+    pub fn set_key(
+        &mut self,
+        note: u8,
+        key_override: &KeyOverride,
+        num_leds: usize,
+    ) -> Result<(), String> {
+        let leds = key_override.leds();
+        let (Some(first), Some(last)) = (leds.iter().min(), leds.iter().max()) else {
+            return Err(format!("Note {} must have at least one LED", note));
+        };
+        if *last >= num_leds {
+            return Err(format!(
+                "Note {} is mapped to LED {}, beyond the {} LEDs of the strip",
+                note, last, num_leds
+            ));
+        }
+        let (start, end) = match key_override {
+            KeyOverride::Leds(_) => (*first as f32, *last as f32 + 1.0),
+            KeyOverride::Span { start, end, .. } => (*start, *end),
+        };
+        if !(0.0 <= start && start < end && end <= num_leds as f32) {
+            return Err(format!(
+                "Note {} spans from {} to {}, out of the {} LEDs of the strip",
+                note, start, end, num_leds
+            ));
+        }
+        let key = note
+            .checked_sub(self.first_note)
+            .and_then(|index| self.keys.get_mut(index as usize))
+            .ok_or_else(|| format!("Note {} is not on the keyboard", note))?;
+        key.start = start;
+        key.end = end;
+        key.leds = leds.to_vec();
+        Ok(())
+    }
+    /// Key playing a note, none for the notes out of the keyboard
+    pub fn key(&self, note: u8) -> Option<&Key> {
+        note.checked_sub(self.first_note)
+            .and_then(|index| self.keys.get(index as usize))
+    }
+    /// LED facing the center of a key
+    pub fn position(&self, note: u8) -> Option<usize> {
+        self.key(note).map(|key| key.center())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 88 keys keyboard is 1222 mm wide, 176 LEDs at 144 LEDs per meter
+    fn mapping(reversed: bool, overrides: &[(&str, KeyOverride)]) -> MappingConfig {
+        MappingConfig {
+            keys: 88,
+            first_note: 21,
+            density: 144.0,
+            offset: 0.0,
+            reversed,
+            overrides: overrides
+                .iter()
+                .map(|(note, key_override)| (note.to_string(), key_override.clone()))
+                .collect(),
+            black_key_brightness: 0.5,
+        }
+    }
+
+    #[test]
+    fn maps_the_keys_along_the_strip() {
+        let key_map = KeyMap::new(&mapping(false, &[]), 180).unwrap();
+        assert_eq!(key_map.keys.len(), 88);
+        assert_eq!(key_map.position(21), Some(1));
+        assert_eq!(key_map.position(108), Some(174));
+        assert_eq!(key_map.position(20), None);
+        assert_eq!(key_map.position(109), None);
+        let a0 = key_map.key(21).unwrap();
+        assert_eq!(a0.span(), 0..4);
+        assert_eq!(a0.brightness, 1.0);
+        assert_eq!(key_map.key(22).unwrap().brightness, 0.5);
+    }
+
+    #[test]
+    fn maps_the_keys_from_the_end_when_reversed() {
+        let key_map = KeyMap::new(&mapping(true, &[]), 180).unwrap();
+        assert_eq!(key_map.position(21), Some(178));
+        assert_eq!(key_map.position(108), Some(5));
+        let positions: Vec<usize> = key_map.keys.iter().map(|key| key.center()).collect();
+        assert!(positions.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn applies_the_overrides() {
+        let key_map = KeyMap::new(
+            &mapping(false, &[("60", KeyOverride::Leds(vec![5, 6]))]),
+            180,
+        )
+        .unwrap();
+        let key = key_map.key(60).unwrap();
+        assert_eq!(key.leds, vec![5, 6]);
+        assert_eq!((key.start, key.end), (5.0, 7.0));
+        assert_eq!(key_map.position(60), Some(5));
+    }
+
+    #[test]
+    fn keeps_the_measured_spans() {
+        let span = KeyOverride::Span {
+            leds: vec![5, 6],
+            start: 4.6,
+            end: 7.2,
+        };
+        let key_map = KeyMap::new(&mapping(false, &[("60", span)]), 180).unwrap();
+        let key = key_map.key(60).unwrap();
+        assert_eq!((key.start, key.end), (4.6, 7.2));
+        assert_eq!(key.span(), 4..8);
+
+        let span = KeyOverride::Span {
+            leds: vec![5, 6],
+            start: 7.2,
+            end: 4.6,
+        };
+        let e = KeyMap::new(&mapping(false, &[("60", span)]), 180).unwrap_err();
+        assert_eq!(
+            e,
+            "Note 60 spans from 7.2 to 4.6, out of the 180 LEDs of the strip"
+        );
+    }
+
+    #[test]
+    fn rejects_keys_beyond_the_strip() {
+        let e = KeyMap::new(&mapping(false, &[]), 100).unwrap_err();
+        assert!(e.contains("beyond the 100 LEDs of the strip"), "{}", e);
+        let e = KeyMap::new(
+            &mapping(false, &[("60", KeyOverride::Leds(vec![180]))]),
+            180,
+        )
+        .unwrap_err();
+        assert_eq!(
+            e,
+            "Note 60 is mapped to LED 180, beyond the 180 LEDs of the strip"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_overrides() {
+        let e =
+            KeyMap::new(&mapping(false, &[("C4", KeyOverride::Leds(vec![5]))]), 180).unwrap_err();
+        assert_eq!(e, "Invalid note `C4` in the overrides");
+        let e =
+            KeyMap::new(&mapping(false, &[("120", KeyOverride::Leds(vec![5]))]), 180).unwrap_err();
+        assert_eq!(e, "Note 120 is not on the keyboard");
+        let e =
+            KeyMap::new(&mapping(false, &[("60", KeyOverride::Leds(vec![]))]), 180).unwrap_err();
+        assert_eq!(e, "Note 60 must have at least one LED");
+    }
+
+    #[test]
+    fn leaves_out_the_leds_off_a_short_strip() {
+        // The strip starts in the middle of the lowest key
+        let key_map = KeyMap::from_positions(60, 2, 10, 1.0, |position| {
+            (position - get_key_center(60)) * 0.25
+        })
+        .unwrap();
+        let c4 = key_map.key(60).unwrap();
+        assert_eq!(c4.leds, vec![0, 1, 2]);
+        assert_eq!(c4.start, 0.0);
+
+        let e = KeyMap::from_positions(60, 2, 10, 1.0, |position| position).unwrap_err();
+        assert!(e.contains("beyond the 10 LEDs of the strip"), "{}", e);
+    }
+}
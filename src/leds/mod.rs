@@ -1 +1,6 @@
+pub mod calibration;
 pub mod functions;
+pub mod mapping;
+pub mod scheduler;
+pub mod state;
+pub mod strip;
@@ -0,0 +1,136 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocket::serde::Serialize;
+
+use crate::structs::NoteTime;
+
+/// Longer pauses, like a stalled render, don't make the animations jump
+const MAX_DELTA: f32 = 0.1;
+/// Period over which the metrics are measured
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Timings of the LED loop, measured over the last second
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct FrameMetrics {
+    pub target_fps: u32,
+    pub fps: f32,
+    /// Average time taken to update, draw and render a frame, in milliseconds
+    pub frame_time: f32,
+    /// Longest frame, in milliseconds
+    pub max_frame_time: f32,
+    /// Frames skipped since the start because the previous ones were late
+    pub dropped_frames: u64,
+    pub frames: u64,
+    /// Average time from playing a note to showing it, in milliseconds
+    pub latency: f32,
+    pub max_latency: f32,
+}
+
+/// Paces the LED loop at a fixed frame rate, whatever the time taken by the
+/// frames themselves, and measures it
+pub struct FrameScheduler {
+    frame_duration: Duration,
+    next_frame: Instant,
+    last_frame: Instant,
+    frame_start: Instant,
+    window_start: Instant,
+    window_frames: u32,
+    window_time: Duration,
+    window_max: Duration,
+    window_notes: u32,
+    window_latency: Duration,
+    window_max_latency: Duration,
+    dropped_frames: u64,
+    frames: u64,
+    metrics: Arc<Mutex<FrameMetrics>>,
+}
+impl FrameScheduler {
+    pub fn new(fps: u32, metrics: &Arc<Mutex<FrameMetrics>>) -> FrameScheduler {
+        let now = Instant::now();
+        metrics
+            .lock()
+            .expect("Could not take the lock on `metrics`")
+            .target_fps = fps;
+        FrameScheduler {
+            frame_duration: Duration::from_secs(1) / fps.max(1),
+            next_frame: now,
+            last_frame: now,
+            frame_start: now,
+            window_start: now,
+            window_frames: 0,
+            window_time: Duration::ZERO,
+            window_max: Duration::ZERO,
+            window_notes: 0,
+            window_latency: Duration::ZERO,
+            window_max_latency: Duration::ZERO,
+            dropped_frames: 0,
+            frames: 0,
+            metrics: metrics.clone(),
+        }
+    }
+    /// Sleeps until the next frame is due, then returns the seconds elapsed
+    /// since the previous one
+    pub fn wait(&mut self) -> f32 {
+        let now = Instant::now();
+        if now < self.next_frame {
+            thread::sleep(self.next_frame - now);
+            self.next_frame += self.frame_duration;
+        } else {
+            // Frames that couldn't start in time are skipped rather than
+            // rendered in a rush to catch up
+            let late = (now - self.next_frame).as_nanos() / self.frame_duration.as_nanos();
+            self.dropped_frames += late as u64;
+            self.next_frame += self.frame_duration * (late as u32 + 1);
+        }
+        let now = Instant::now();
+        let delta = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        self.frame_start = now;
+        delta.min(MAX_DELTA)
+    }
+    /// Records the time taken by the frame once rendered, and the latency of
+    /// the notes it showed from the times they happened at
+    pub fn end_frame(&mut self, received: &[NoteTime]) {
+        let frame_time = self.frame_start.elapsed();
+        for received in received.iter() {
+            let latency = received.elapsed();
+            self.window_notes += 1;
+            self.window_latency += latency;
+            self.window_max_latency = self.window_max_latency.max(latency);
+        }
+        self.frames += 1;
+        self.window_frames += 1;
+        self.window_time += frame_time;
+        self.window_max = self.window_max.max(frame_time);
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed < WINDOW {
+            return;
+        }
+        // Published on a later frame rather than waiting for the API to
+        // release the metrics
+        let Ok(mut metrics) = self.metrics.try_lock() else {
+            return;
+        };
+        metrics.fps = self.window_frames as f32 / elapsed.as_secs_f32();
+        metrics.frame_time = self.window_time.as_secs_f32() * 1000.0 / self.window_frames as f32;
+        metrics.max_frame_time = self.window_max.as_secs_f32() * 1000.0;
+        metrics.dropped_frames = self.dropped_frames;
+        metrics.frames = self.frames;
+        // Kept from the last notes when nothing is played
+        if self.window_notes > 0 {
+            metrics.latency = self.window_latency.as_secs_f32() * 1000.0 / self.window_notes as f32;
+            metrics.max_latency = self.window_max_latency.as_secs_f32() * 1000.0;
+        }
+        self.window_start = Instant::now();
+        self.window_frames = 0;
+        self.window_time = Duration::ZERO;
+        self.window_max = Duration::ZERO;
+        self.window_notes = 0;
+        self.window_latency = Duration::ZERO;
+        self.window_max_latency = Duration::ZERO;
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use rocket::tokio::sync::oneshot;
+
+use paris::warn;
+
+use super::{calibration::Calibration, mapping::KeyMap};
+use crate::structs::{Animator, ColorMode, Config, LedsConfig};
+
+/// Animation state of the strip. The LED thread is its single owner: nothing
+/// else can lock or read it, the other threads queue commands through a
+/// `LedHandle` that the LED thread applies between two frames. Rendering
+/// never waits on them.
+pub struct LedState {
+    pub animator: Animator,
+    pub color_mode: ColorMode,
+    pub key_map: KeyMap,
+    /// Replaces the animations while the keys are being mapped
+    pub calibration: Option<Calibration>,
+    pub config: LedsConfig,
+}
+impl LedState {
+    /// Changes the color mode, crossfading the layers to it
+    pub fn set_color_mode(&mut self, mode: String) {
+        self.color_mode.set_color_mode(mode);
+        self.animator.set_color_mode(&self.color_mode);
+    }
+    /// Applies a reloaded config. Only what changed in it is applied, what
+    /// was changed at runtime is kept otherwise.
+    pub fn apply_config(&mut self, config: &Config) {
+        let (old, new) = (self.config.clone(), &config.leds);
+        if old.num_leds != new.num_leds {
+            // Everything is sized after the strip
+            let harmony = self.color_mode.harmony.clone();
+            self.color_mode = ColorMode::new(&new.color_mode, &new.num_leds);
+            self.color_mode.harmony = harmony;
+            self.animator = Animator::new(config, &new.animation);
+            self.animator.set_color_mode(&self.color_mode);
+        } else {
+            self.animator.set_config(config);
+            if old.animation != new.animation {
+                if let Err(e) = self.animator.set_animation(new.animation.to_string()) {
+                    warn!("<yellow>[WS2812]</> {}", e);
+                }
+            }
+            if old.color_mode != new.color_mode {
+                self.set_color_mode(new.color_mode.to_string());
+            }
+        }
+        if old.mapping != new.mapping || old.num_leds != new.num_leds {
+            self.key_map = KeyMap::new(&new.mapping, new.num_leds)
+                .expect("The key mapping should have been validated");
+            self.calibration = None;
+        }
+        self.config = new.clone();
+    }
+    /// Applies every queued command, in the order they were sent
+    pub fn apply_commands(&mut self, commands: &Receiver<Command>) {
+        for command in commands.try_iter() {
+            command(self);
+        }
+    }
+}
+
+pub type Command = Box<dyn FnOnce(&mut LedState) + Send>;
+/// Settings to open the strip with, and where to answer whether it opened
+pub type StripRequest = (LedsConfig, Sender<Result<(), String>>);
+
+/// Queues commands to the LED thread
+#[derive(Clone)]
+pub struct LedHandle {
+    commands: Sender<Command>,
+    strips: Sender<StripRequest>,
+}
+impl LedHandle {
+    pub fn new(commands: Sender<Command>, strips: Sender<StripRequest>) -> LedHandle {
+        LedHandle { commands, strips }
+    }
+    /// Opens the strip again with the pin, channel and number of LEDs of
+    /// `config`, the current one being kept if it fails. Blocks until the LED
+    /// thread did it.
+    pub fn reopen_strip(&self, config: &LedsConfig) -> Result<(), String> {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        self.strips
+            .send((config.clone(), reply_tx))
+            .map_err(|_| "The LED thread stopped".to_string())?;
+        reply_rx
+            .recv()
+            .map_err(|_| "The LED thread stopped".to_string())?
+    }
+    /// Runs `command` on the LED thread before its next frame, without
+    /// waiting for it
+    pub fn send<F>(&self, command: F)
+    where
+        F: FnOnce(&mut LedState) + Send + 'static,
+    {
+        self.commands
+            .send(Box::new(command))
+            .expect("The LED thread stopped");
+    }
+    /// Runs `command` on the LED thread before its next frame, and waits for
+    /// its result without blocking the caller's thread
+    pub async fn run<T, F>(&self, command: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut LedState) -> T + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.commands
+            .send(Box::new(move |state| {
+                // The caller may have given up waiting, the result is then dropped
+                let _ = reply_tx.send(command(state));
+            }))
+            .expect("The LED thread stopped");
+        reply_rx.await.expect("The LED thread stopped")
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use rs_ws281x::{Controller, RawColor};
+
+use super::functions::create_controller;
+use crate::structs::LedsConfig;
+
+/// Columns of the terminal the simulated strip is printed on, neighbouring
+/// LEDs being averaged to fit
+const SIMULATED_WIDTH: usize = 100;
+const SIMULATED_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Prints the frames to the terminal, to run without a strip
+pub struct SimulatedStrip {
+    leds: Vec<RawColor>,
+    brightness: u8,
+    printed: Option<Instant>,
+}
+impl SimulatedStrip {
+    fn print(&mut self) {
+        if self
+            .printed
+            .is_some_and(|printed| printed.elapsed() < SIMULATED_INTERVAL)
+        {
+            return;
+        }
+        self.printed = Some(Instant::now());
+        let mut line = String::from("\r");
+        for leds in self.leds.chunks(self.leds.len().div_ceil(SIMULATED_WIDTH)) {
+            // RawColor is [B, G, R, W], the white LED adding to every channel
+            let channel = |index: usize| {
+                let sum: u32 = leds
+                    .iter()
+                    .map(|led| (led[index] as u32 + led[3] as u32).min(255))
+                    .sum();
+                sum / leds.len() as u32 * self.brightness as u32 / 255
+            };
+            line += &format!("\x1b[38;2;{};{};{}m█", channel(2), channel(1), channel(0));
+        }
+        line += "\x1b[0m";
+        print!("{}", line);
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Where the frames go
+pub enum Strip {
+    Hardware(Controller),
+    Simulated(SimulatedStrip),
+}
+impl Strip {
+    pub fn open(config: &LedsConfig, simulated: bool) -> Result<Strip, String> {
+        if simulated {
+            Ok(Strip::Simulated(SimulatedStrip {
+                leds: vec![[0, 0, 0, 0]; config.num_leds],
+                brightness: config.brightness,
+                printed: None,
+            }))
+        } else {
+            create_controller(config).map(Strip::Hardware)
+        }
+    }
+    pub fn is_simulated(&self) -> bool {
+        matches!(self, Strip::Simulated(_))
+    }
+    pub fn leds_mut(&mut self, channel: usize) -> &mut [RawColor] {
+        match self {
+            Strip::Hardware(controller) => controller.leds_mut(channel),
+            Strip::Simulated(strip) => &mut strip.leds,
+        }
+    }
+    pub fn set_brightness(&mut self, channel: usize, brightness: u8) {
+        match self {
+            Strip::Hardware(controller) => controller.set_brightness(channel, brightness),
+            Strip::Simulated(strip) => strip.brightness = brightness,
+        }
+    }
+    pub fn render(&mut self) {
+        match self {
+            Strip::Hardware(controller) => controller.render().expect("Couldn't render"),
+            Strip::Simulated(strip) => strip.print(),
+        }
+    }
+}
@@ -1,25 +1,54 @@
 #![feature(proc_macro_hygiene, decl_macro)]
 #[macro_use]
 extern crate rocket;
+mod animations;
 mod api;
+mod cli;
+mod config;
 mod functions;
+mod harmony;
 mod leds;
 mod midi;
+mod reload;
+mod scenes;
+mod settings;
+mod stats;
 mod structs;
 
+use animations::heatmap::{serve_history, HistoryRequest};
+use api::{web::WebRoot, AppState};
 use cichlid::{prelude::*, ColorRGB};
-use leds::functions::*;
-use midi::functions::*;
-use paris::{error, info, success};
-use portmidi::{MidiEvent, PortMidi};
-use rs_ws281x::{ChannelBuilder, ControllerBuilder, StripType};
+use cli::{list_devices, load_config_or_exit, print_config, Args, RunArgs, Subcommand};
+use harmony::Harmony;
+use leds::{
+    functions::*,
+    mapping::KeyMap,
+    scheduler::{FrameMetrics, FrameScheduler},
+    state::{Command, LedHandle, LedState, StripRequest},
+    strip::Strip,
+};
+use midi::{functions::*, recorder::Recorder, sessions::Sessions};
+use paris::{error, info, warn};
+use portmidi::PortMidi;
+use reload::{watch_config, Reloader};
+use rocket::tokio::signal::unix::{signal, SignalKind};
+use scenes::{apply_scene, SceneStore};
+use settings::load_settings;
+use stats::StatsStore;
 use std::{
-    fs,
     panic::set_hook,
-    sync::{Arc, Mutex},
-    thread, time,
+    process,
+    sync::{mpsc::Receiver, Arc, Mutex},
+    thread,
+    time::Duration,
 };
 use structs::*;
+
+/// Left for the last notes of a played file to fade out
+const PLAY_FADE_OUT: Duration = Duration::from_secs(2);
+/// How often the sessions over are looked for, to be saved
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
 #[rocket::main]
 async fn main() {
     set_hook(Box::new(|info| {
@@ -28,79 +57,263 @@ async fn main() {
         }
     }));
 
-    let config_file = fs::read_to_string("config.toml").expect("Couldn't read config.toml");
-    let config: Config = toml::from_str(&config_file).expect("Couldn't parse config.toml");
+    let args: Args = argh::from_env();
+    let paths = args.paths();
+    // Kept from before the subcommands, for the scripts using it
+    if args.check_config {
+        print_config(&paths, true);
+        return;
+    }
+    match args.command.unwrap_or(Subcommand::Run(RunArgs::default())) {
+        Subcommand::Run(run_args) => run(&paths, run_args.simulate).await,
+        Subcommand::Devices(_) => list_devices(),
+        Subcommand::Play(play_args) => play(&paths, &play_args.file, play_args.simulate),
+        Subcommand::Calibrate(calibrate_args) => calibrate(&paths, calibrate_args.simulate).await,
+        Subcommand::Config(config_args) => print_config(&paths, config_args.check),
+    }
+}
+
+/// Builds the animation state and moves it to a new LED thread, its only
+/// owner. The heatmaps start from `stats` when given.
+fn start_leds(
+    config: &Config,
+    metrics: &Arc<Mutex<FrameMetrics>>,
+    stats: Option<&Arc<Mutex<StatsStore>>>,
+    midi_rx: Receiver<NoteEvent>,
+    simulate: bool,
+) -> LedHandle {
+    let color_mode = ColorMode::new(&config.leds.color_mode, &config.leds.num_leds);
+    let mut animator = Animator::new(config, &config.leds.animation);
+    animator.set_color_mode(&color_mode);
+    let key_map = KeyMap::new(&config.leds.mapping, config.leds.num_leds)
+        .expect("The key mapping should have been validated");
+    let mut led_state = LedState {
+        animator,
+        color_mode,
+        key_map,
+        calibration: None,
+        config: config.leds.clone(),
+    };
+    let (commands_tx, commands_rx) = std::sync::mpsc::channel::<Command>();
+    let (strips_tx, strips_rx) = std::sync::mpsc::channel::<StripRequest>();
+    let handle = LedHandle::new(commands_tx, strips_tx);
+
+    let (history_tx, history_rx) = std::sync::mpsc::channel::<HistoryRequest>();
+    if let Some(stats) = stats {
+        let (stats, leds) = (stats.clone(), handle.clone());
+        thread::spawn(move || serve_history(&stats, &leds, history_rx));
+    }
+
+    let config = config.clone();
+    let metrics = metrics.clone();
+    thread::spawn(move || {
+        info!("<blue>[WS2812]</> Starting the thread");
+        let mut strip = Strip::open(&config.leds, simulate).expect("Couldn't open the strip");
+        let mut colors = vec![ColorRGB::Black; config.leds.num_leds];
+        colors.rainbow_fill(0, (config.leds.num_leds * 4) as u16);
+
+        let mut scheduler = FrameScheduler::new(config.leds.fps, &metrics);
+        // Settings the strip was opened with
+        let mut opened = config.leds.clone();
+        loop {
+            let delta = scheduler.wait();
+            // Asked by a reloaded config before its other LED settings
+            for (leds, reply) in strips_rx.try_iter() {
+                info!("<blue>[WS2812]</> Reopening the strip");
+                // The current strip is only dropped once the new one opened
+                let reopened = Strip::open(&leds, strip.is_simulated()).map(|new| strip = new);
+                let _ = reply.send(reopened);
+            }
+            let received = animate_strip(&mut led_state, &mut strip, &commands_rx, &midi_rx, delta);
+            scheduler.end_frame(&received);
+            for request in led_state.animator.history_requests() {
+                // Nothing answers them without the stats, like when playing a file
+                let _ = history_tx.send(request);
+            }
+
+            // Applies the settings of a reloaded config
+            let leds = &led_state.config;
+            if *leds != opened {
+                if leds.brightness != opened.brightness {
+                    strip.set_brightness(leds.channel, leds.brightness);
+                }
+                if leds.fps != opened.fps {
+                    scheduler = FrameScheduler::new(leds.fps, &metrics);
+                }
+                opened = leds.clone();
+            }
+        }
+    });
+    handle
+}
+
+/// Config file with the settings saved through the API applied over it
+fn load_effective_config(paths: &Paths) -> Config {
+    let file_config = load_config_or_exit(paths);
+    load_settings(&paths.settings, &file_config).apply(&file_config)
+}
+
+/// Plays a MIDI file to the strip, without the keyboard nor the API
+fn play(paths: &Paths, file: &str, simulate: bool) {
+    let config = load_effective_config(paths);
+    let metrics = Arc::new(Mutex::new(FrameMetrics::default()));
+    let (midi_tx, midi_rx) = std::sync::mpsc::channel::<NoteEvent>();
+    let _leds = start_leds(&config, &metrics, None, midi_rx, simulate);
+    if let Err(e) = play_midi_file(file.to_string(), None, midi_tx.clone(), &config) {
+        error!("<red>[MIDI]</> {}", e);
+        process::exit(1);
+    }
+    thread::sleep(PLAY_FADE_OUT);
+}
+
+/// Maps the keys to the LEDs from the terminal, without the API
+async fn calibrate(paths: &Paths, simulate: bool) {
+    let config = load_effective_config(paths);
+    let metrics = Arc::new(Mutex::new(FrameMetrics::default()));
+    let (midi_tx, midi_rx) = std::sync::mpsc::channel::<NoteEvent>();
+    let leds = start_leds(&config, &metrics, None, midi_rx, simulate);
+
     let config_midi = config.clone();
-    let config_leds = config.clone();
+    thread::spawn(move || {
+        let midi_context = PortMidi::new().expect("Couldn't create PortMidi context");
+        match open_input_port(&midi_context, &config_midi) {
+            Ok(input_port) => forward_notes(&input_port, &midi_tx, &config_midi),
+            Err(e) => error!("<red>[MIDI]</> {}", e),
+        };
+    });
+    cli::calibrate(&leds, paths).await;
+}
 
-    let color_mode = Arc::new(Mutex::new(ColorMode::new(
-        &config.leds.color_mode,
-        &config.leds.num_leds,
-    )));
-    let color_mode_leds = color_mode.clone();
+/// Starts every thread and the API
+async fn run(paths: &Paths, simulate: bool) {
+    let file_config = load_config_or_exit(paths);
+    // Changed through the API since, and restored
+    let settings = load_settings(&paths.settings, &file_config);
+    let config = settings.apply(&file_config);
+    let config_midi = config.clone();
+
+    let harmony = Arc::new(Mutex::new(Harmony::new(&config)));
+    let harmony_midi = harmony.clone();
+
+    let recorder = Arc::new(Mutex::new(Recorder::new(&config)));
+    let recorder_midi = recorder.clone();
+
+    let sessions = Arc::new(Mutex::new(Sessions::new(&config)));
+    let sessions_midi = sessions.clone();
+
+    let stats = Arc::new(Mutex::new(StatsStore::new(&config)));
+    let stats_midi = stats.clone();
+
+    let (sessions_save, stats_save) = (sessions.clone(), stats.clone());
+    thread::spawn(move || loop {
+        save_sessions(&sessions_save, &stats_save, false);
+        thread::sleep(SAVE_INTERVAL);
+    });
+    let (sessions_shutdown, stats_shutdown) = (sessions.clone(), stats.clone());
+
+    let metrics = Arc::new(Mutex::new(FrameMetrics::default()));
 
-    let animator = Arc::new(Mutex::new(Animator::new(&config, &config.leds.animation)));
-    let animator_leds = animator.clone();
+    let (midi_tx, midi_rx) = std::sync::mpsc::channel::<NoteEvent>();
+    let midi_tx_api = midi_tx.clone();
 
-    let (midi_tx, midi_rx) = std::sync::mpsc::channel::<(MidiEventType, MidiEvent, usize)>();
+    let scenes = Arc::new(Mutex::new(SceneStore::new(&paths.scenes)));
+    let scenes_recall = scenes.clone();
+    let (programs_tx, programs_rx) = std::sync::mpsc::channel::<u8>();
 
+    let leds = start_leds(&config, &metrics, Some(&stats), midi_rx, simulate);
+    let leds_midi = leds.clone();
+
+    let (midi_config_tx, midi_config_rx) = std::sync::mpsc::channel::<Config>();
     thread::spawn(move || {
-        let config = config_midi;
+        let mut config = config_midi;
         info!("<blue>[MIDI]</> Starting the thread");
 
         let midi_context = PortMidi::new().expect("Couldn't create PortMidi context");
-        let device_info = midi_context
-            .device(config.midi.id)
-            .expect(format!("Could not find device with id {}", config.midi.id).as_str());
-        info!(
-            "<blue>[MIDI]</> Using device {}) {}",
-            device_info.id(),
-            device_info.name()
-        );
-        let input_port = midi_context
-            .input_port(device_info, config.midi.buffer_size)
-            .expect("Could not create input port");
-
-        watch_midi(&input_port, &midi_tx, &config)
+        loop {
+            match open_input_port(&midi_context, &config) {
+                Ok(input_port) => watch_midi(
+                    &input_port,
+                    &midi_tx,
+                    &recorder_midi,
+                    &sessions_midi,
+                    &stats_midi,
+                    &harmony_midi,
+                    &leds_midi,
+                    &programs_tx,
+                    &midi_config_rx,
+                    &mut config,
+                ),
+                // Retried with the next reloaded config
+                Err(e) => {
+                    error!("<red>[MIDI]</> {}", e);
+                    config = midi_config_rx.recv().expect("The config reloader stopped");
+                }
+            }
+            info!("<blue>[MIDI]</> Reopening the device");
+        }
     });
 
+    let reloader = Arc::new(Mutex::new(Reloader::new(
+        paths,
+        &file_config,
+        settings,
+        leds.clone(),
+        midi_config_tx,
+        &harmony,
+    )));
+    let reloader_watch = reloader.clone();
+    let reloader_scenes = reloader.clone();
+    let leds_scenes = leds.clone();
+    let (reload_tx, reload_rx) = std::sync::mpsc::channel::<()>();
+    thread::spawn(move || watch_config(&reloader_watch, &reload_rx));
     thread::spawn(move || {
-        let config = config_leds;
-        let color_mode = color_mode_leds;
-        let animator = animator_leds;
-        info!("<blue>[WS2812]</> Starting the thread");
-        let mut controller = ControllerBuilder::new()
-            .freq(800_000)
-            .dma(10)
-            .channel(
-                config.leds.channel,
-                ChannelBuilder::new()
-                    .pin(config.leds.pin)
-                    .count(config.leds.num_leds as i32)
-                    .strip_type(StripType::Ws2812)
-                    .brightness(config.leds.brightness)
-                    .build(),
-            )
-            .build()
-            .expect("Couldn't create controller");
-        success!(
-            "<green>[WS2812]</> Created controller on pin {}",
-            config.leds.pin
-        );
-        let mut colors = vec![ColorRGB::Black; config.leds.num_leds];
-        colors.rainbow_fill(0, (config.leds.num_leds * 4) as u16);
-
-        loop {
-            animate_strip(&animator, &mut controller, &midi_rx, &color_mode);
-            thread::sleep(time::Duration::from_millis(config.midi.timeout));
+        for program in programs_rx {
+            let scene = scenes_recall
+                .lock()
+                .expect("Couldn't lock the scenes")
+                .by_program(program)
+                .cloned();
+            let Some(scene) = scene else {
+                warn!("<yellow>[Scenes]</> No scene for program {}", program);
+                continue;
+            };
+            info!("<blue>[Scenes]</> Recalling `{}`", scene.name);
+            if let Err(e) = apply_scene(&scene, &reloader_scenes, &leds_scenes) {
+                error!("<red>[Scenes]</> {}", e);
+            }
+        }
+    });
+    rocket::tokio::spawn(async move {
+        let mut hangups = signal(SignalKind::hangup()).expect("Couldn't listen to SIGHUP");
+        while hangups.recv().await.is_some() {
+            info!("<blue>[Config]</> Received SIGHUP, reloading");
+            if reload_tx.send(()).is_err() {
+                break;
+            }
         }
     });
 
-    let _ = crate::api::main(&color_mode, &animator)
-        .ignite()
-        .await
-        .expect("Couldn't ignite the API")
-        .launch()
-        .await
-        .expect("Couldn't launch the API");
+    let web_root = WebRoot::new(&config.api);
+    let _ = crate::api::main(
+        AppState {
+            leds,
+            reloader,
+            recorder,
+            sessions,
+            stats,
+            scenes,
+            harmony,
+            metrics,
+            midi_tx: midi_tx_api,
+        },
+        web_root,
+    )
+    .ignite()
+    .await
+    .expect("Couldn't ignite the API")
+    .launch()
+    .await
+    .expect("Couldn't launch the API");
+    // The session being played isn't lost when quitting
+    save_sessions(&sessions_shutdown, &stats_shutdown, true);
 }
@@ -1,190 +1,253 @@
 use core::time;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::{fs, thread};
 
-use crate::leds::functions::get_note_position;
-use crate::structs::{Config, MidiEventType};
-use paris::{info, log};
+use crate::harmony::{Harmony, HarmonySnapshot};
+use crate::leds::state::LedHandle;
+use crate::midi::recorder::Recorder;
+use crate::midi::sessions::Sessions;
+use crate::stats::StatsStore;
+use crate::structs::{Config, MidiEventType, NoteEvent};
+use paris::{error, info, log};
 use pm::MidiMessage;
 use portmidi as pm;
 use portmidi::MidiEvent;
 
+#[link(name = "portmidi")]
+extern "C" {
+    fn Pt_Time() -> i32;
+}
+/// Clock of PortMidi in milliseconds, which timestamps the events of the
+/// devices. It's started when the first device is opened.
+pub fn midi_time() -> u32 {
+    unsafe { Pt_Time() as u32 }
+}
+
 pub fn get_midi_event_type(status: u8, velocity: u8) -> MidiEventType {
     if status == 144 && velocity > 0 {
         MidiEventType::NoteOn
     } else if status == 128 || (status == 144 && velocity == 0) {
         MidiEventType::NoteOff
+    } else if status & 0xf0 == 0xc0 {
+        MidiEventType::ProgramChange
     } else {
         MidiEventType::ControlChange
     }
 }
+/// Microseconds per beat of the files setting no tempo
+const DEFAULT_BEAT_DURATION_US: u64 = 500_000;
+
+/// Plays a MIDI file to the strip, and also to the output device next to the
+/// input one when a `midi_context` is given.
 pub fn play_midi_file(
     file: String,
-    midi_context: &pm::PortMidi,
-    tx: Sender<(MidiEventType, pm::MidiEvent, usize)>,
+    midi_context: Option<&pm::PortMidi>,
+    tx: Sender<NoteEvent>,
     config: &Config,
-) {
-    let mut out_port = midi_context
-        .device(config.midi.id - 1)
-        .and_then(|dev| midi_context.output_port(dev, 1024))
-        .unwrap();
-    let midi_data = fs::read(file).unwrap();
-    let smf = midly::Smf::parse(&midi_data).unwrap();
+) -> Result<(), String> {
+    let mut out_port = match midi_context {
+        Some(midi_context) => Some(
+            midi_context
+                .device(config.midi.id - 1)
+                .and_then(|dev| midi_context.output_port(dev, 1024))
+                .map_err(|e| format!("Could not create output port: {}", e))?,
+        ),
+        None => None,
+    };
+    let midi_data = fs::read(&file).map_err(|e| format!("Couldn't read {}: {}", file, e))?;
+    let smf =
+        midly::Smf::parse(&midi_data).map_err(|e| format!("Couldn't parse {}: {}", file, e))?;
     info!("<blue>[MIDI]</> Parsed SMF");
     info!("<blue>[MIDI]</> {} tracks", smf.tracks.len());
-    let mut ticks_per_beat: u64 = 0;
-    let mut beat_duration_us: u64 = 0;
-    match smf.header.timing {
-        midly::Timing::Metrical(ref timing) => {
-            ticks_per_beat = timing.as_int().into();
-            println!("[MIDI] Ticks per beat: {:?}/beat", ticks_per_beat);
-        }
-        midly::Timing::Timecode(fps, sub) => {
-            println!("[MIDI] TimeCode timing: fps {:?} | sub {:?}", fps, sub);
-        }
-    }
-    match smf.header.format {
-        midly::Format::SingleTrack => {
-            info!("<blue>[MIDI]</> Single track");
-        }
-        midly::Format::Parallel => {
-            info!("<blue>[MIDI]</> Parallel track");
-        }
-        midly::Format::Sequential => {
-            info!("<blue>[MIDI]</> Sequential track");
-        }
-    }
-    // Tempo track
-    for &event in smf.tracks[0].iter() {
-        match event.kind {
-            midly::TrackEventKind::Meta(ref meta) => match meta {
-                midly::MetaMessage::Tempo(tempo) => {
-                    beat_duration_us = tempo.as_int() as u64;
-                    info!(
-                        "<blue>[MIDI]</> Microseconds per beat: {}",
-                        beat_duration_us
-                    );
-                }
+    // Metrical files count in beats, whose duration the tempo sets, the
+    // others directly in fractions of a second
+    let (ticks_per_beat, tick_duration_us) = match smf.header.timing {
+        midly::Timing::Metrical(timing) if timing.as_int() > 0 => (timing.as_int() as u64, None),
+        midly::Timing::Metrical(_) => return Err(format!("{} has no ticks per beat", file)),
+        midly::Timing::Timecode(fps, sub) => (0, Some(1e6 / (fps.as_f32() * sub as f32))),
+    };
+    let mut beat_duration_us = DEFAULT_BEAT_DURATION_US;
 
-                _ => {}
+    let mut play = |event_type, message: MidiMessage| -> Result<(), String> {
+        if let Some(out_port) = out_port.as_mut() {
+            out_port
+                .write_message(message)
+                .map_err(|e| format!("Could not write to the output port: {}", e))?;
+        }
+        tx.send(NoteEvent::new(
+            event_type,
+            MidiEvent {
+                message,
+                timestamp: 0,
             },
+        ))
+        .map_err(|_| "The LEDs stopped".to_string())
+    };
+    let mut last_tick = 0;
+    for (tick, kind) in merge_tracks(&smf) {
+        let ticks_to_wait = tick - last_tick;
+        last_tick = tick;
+        let wait_us = match tick_duration_us {
+            Some(tick_duration_us) => (ticks_to_wait as f32 * tick_duration_us) as u64,
+            None => ticks_to_wait * beat_duration_us / ticks_per_beat,
+        };
+        thread::sleep(time::Duration::from_micros(wait_us));
+        match kind {
+            midly::TrackEventKind::Meta(midly::MetaMessage::Tempo(tempo)) => {
+                beat_duration_us = tempo.as_int() as u64;
+                info!(
+                    "<blue>[MIDI]</> Microseconds per beat: {}",
+                    beat_duration_us
+                );
+            }
+            midly::TrackEventKind::Meta(midly::MetaMessage::TrackName(name)) => {
+                log!(
+                    "<gray>[MIDI]</> Track name: {}",
+                    String::from_utf8_lossy(name)
+                );
+            }
+            midly::TrackEventKind::Meta(midly::MetaMessage::InstrumentName(name)) => {
+                log!(
+                    "<gray>[MIDI]</> Instrument name: {}",
+                    String::from_utf8_lossy(name)
+                );
+            }
+            midly::TrackEventKind::Midi {
+                message: midly::MidiMessage::NoteOn { key, vel },
+                ..
+            } => {
+                let note_on = MidiMessage {
+                    status: 0x90,
+                    data1: key.into(),
+                    data2: vel.into(),
+                    data3: 0,
+                };
+                if vel > 0 {
+                    play(MidiEventType::NoteOn, note_on)?;
+                } else {
+                    play(MidiEventType::NoteOff, note_on)?;
+                }
+            }
+            midly::TrackEventKind::Midi {
+                message: midly::MidiMessage::NoteOff { key, vel },
+                ..
+            } => {
+                let note_off = MidiMessage {
+                    status: 0x80,
+                    data1: key.into(),
+                    data2: vel.into(),
+                    data3: 0,
+                };
+                play(MidiEventType::NoteOff, note_off)?;
+            }
             _ => {}
         }
     }
-    for (i, track) in smf.tracks.iter().enumerate() {
-        println!("Track {} has {} events", i, track.len());
-        for &event in track.iter() {
-            match event.kind {
-                midly::TrackEventKind::Meta(ref meta) => match meta {
-                    midly::MetaMessage::InstrumentName(ref name) => {
-                        // convert from [u8] to string
-                        let name = String::from_utf8(name.to_vec()).unwrap();
-                        log!("<gray>[MIDI]</> Instrument name: {}", name);
-                    }
-                    midly::MetaMessage::TrackName(ref name) => {
-                        // convert from [u8] to string
-                        let name = String::from_utf8(name.to_vec()).unwrap();
-                        log!("<gray>[MIDI]</> Track name: {}", name);
-                    }
-                    _ => {}
-                },
-                _ => {}
-            }
+    Ok(())
+}
+/// Events of every track by the tick they happen at. The tracks of a
+/// sequential file follow each other, the others are played together.
+fn merge_tracks<'a>(smf: &midly::Smf<'a>) -> Vec<(u64, midly::TrackEventKind<'a>)> {
+    let mut events = Vec::new();
+    let mut start = 0;
+    for track in smf.tracks.iter() {
+        let mut tick = start;
+        for event in track.iter() {
+            tick += event.delta.as_int() as u64;
+            events.push((tick, event.kind));
+        }
+        if smf.header.format == midly::Format::Sequential {
+            start = tick;
         }
     }
-    let track = &smf.tracks[1];
+    // Stable, the events of a tick keeping the order of their tracks
+    events.sort_by_key(|(tick, _)| *tick);
+    events
+}
+/// Opens the configured input device
+pub fn open_input_port<'a>(
+    midi_context: &'a pm::PortMidi,
+    config: &Config,
+) -> Result<pm::InputPort<'a>, String> {
+    let device_info = midi_context
+        .device(config.midi.id)
+        .map_err(|e| format!("Could not find device with id {}: {}", config.midi.id, e))?;
     info!(
-        "<blue>[MIDI]</> Processing track with {} events",
-        track.len()
+        "<blue>[MIDI]</> Using device {}) {}",
+        device_info.id(),
+        device_info.name()
     );
-    for &event in track.iter() {
-        let ticks_to_wait: u64 = event.delta.as_int().into();
-        thread::sleep(time::Duration::from_micros(
-            ticks_to_wait * beat_duration_us / ticks_per_beat,
-        ));
-        match event.kind {
-            midly::TrackEventKind::Midi { message, .. } => match message {
-                midly::MidiMessage::NoteOn { key, vel } => {
-                    let note_on = MidiMessage {
-                        status: 0x90 + 0,
-                        data1: key.into(),
-                        data2: vel.into(),
-                        data3: 0,
-                    };
-                    out_port.write_message(note_on).unwrap();
-                    if vel > 0 {
-                        tx.send((
-                            MidiEventType::NoteOn,
-                            MidiEvent {
-                                message: note_on,
-                                timestamp: 0,
-                            },
-                            get_note_position(key.into(), config),
-                        ))
-                        .unwrap();
-                    } else {
-                        tx.send((
-                            MidiEventType::NoteOff,
-                            MidiEvent {
-                                message: note_on,
-                                timestamp: 0,
-                            },
-                            get_note_position(key.into(), config),
-                        ))
-                        .unwrap();
-                    }
-                }
-                midly::MidiMessage::NoteOff { key, vel } => {
-                    let note_off = MidiMessage {
-                        status: 0x80 + 0,
-                        data1: key.into(),
-                        data2: vel.into(),
-                        data3: 0,
-                    };
-
-                    out_port.write_message(note_off).unwrap();
-                    tx.send((
-                        MidiEventType::NoteOff,
-                        MidiEvent {
-                            message: note_off,
-                            timestamp: 0,
-                        },
-                        get_note_position(key.into(), config),
-                    ))
-                    .unwrap();
-                }
-                _ => {}
-            },
-            _ => {}
-        }
-    }
+    midi_context
+        .input_port(device_info, config.midi.buffer_size)
+        .map_err(|e| format!("Could not create input port: {}", e))
 }
+/// Forwards the notes of the device and the harmony they make to the LED
+/// thread, and its program changes to `programs` to recall the scenes, until
+/// a reloaded config changes the device, which must then be reopened. The
+/// other changes are applied to `config`.
+#[allow(clippy::too_many_arguments)]
 pub fn watch_midi(
     input_port: &pm::InputPort,
-    tx: &Sender<(MidiEventType, pm::MidiEvent, usize)>,
-    config: &Config,
+    tx: &Sender<NoteEvent>,
+    recorder: &Arc<Mutex<Recorder>>,
+    sessions: &Arc<Mutex<Sessions>>,
+    stats: &Arc<Mutex<StatsStore>>,
+    harmony: &Arc<Mutex<Harmony>>,
+    leds: &LedHandle,
+    programs: &Sender<u8>,
+    updates: &Receiver<Config>,
+    config: &mut Config,
 ) {
+    let mut last_snapshot = HarmonySnapshot::default();
     loop {
+        if let Some(update) = updates.try_iter().last() {
+            let reopen = (update.midi.id, update.midi.buffer_size)
+                != (config.midi.id, config.midi.buffer_size);
+            *config = update;
+            if reopen {
+                return;
+            }
+        }
         if let Ok(_) = input_port.poll() {
             if let Ok(Some(events)) = input_port.read_n(config.midi.max_keys_processing) {
                 for event in events {
+                    recorder
+                        .lock()
+                        .expect("Couldn't lock the recorder")
+                        .record(&event);
+                    sessions
+                        .lock()
+                        .expect("Couldn't lock the sessions")
+                        .record(&event);
+                    stats
+                        .lock()
+                        .expect("Couldn't lock the stats")
+                        .record(&event);
+                    let snapshot = {
+                        let mut harmony = harmony.lock().expect("Couldn't lock the harmony");
+                        harmony.handle_event(&event);
+                        harmony.snapshot()
+                    };
+                    // The LED thread colors the notes with its own copy
+                    if snapshot != last_snapshot {
+                        last_snapshot = snapshot.clone();
+                        leds.send(move |leds| leds.color_mode.harmony = snapshot);
+                    }
                     let event_type = get_midi_event_type(event.message.status, event.message.data2);
                     match event_type {
                         MidiEventType::NoteOn => {
-                            tx.send((
-                                event_type,
-                                event,
-                                get_note_position(event.message.data1, &config),
-                            ))
-                            .expect("Failed to send MIDI event");
+                            tx.send(NoteEvent::from_device(event_type, event))
+                                .expect("Failed to send MIDI event");
                         }
                         MidiEventType::NoteOff => {
-                            tx.send((
-                                event_type,
-                                event,
-                                get_note_position(event.message.data1, &config),
-                            ))
-                            .expect("Failed to send MIDI event");
+                            tx.send(NoteEvent::from_device(event_type, event))
+                                .expect("Failed to send MIDI event");
+                        }
+                        MidiEventType::ProgramChange => {
+                            programs
+                                .send(event.message.data1 & 0x7f)
+                                .expect("The scene recall stopped");
                         }
                         _ => {}
                     }
@@ -194,3 +257,42 @@ pub fn watch_midi(
         thread::sleep(time::Duration::from_millis(config.midi.timeout));
     }
 }
+/// Writes the session and the stats once the piano has been silent long
+/// enough, or right away when `now` like on shutdown. The stores are only
+/// locked to take them out, the MIDI thread doesn't wait for the disk.
+pub fn save_sessions(sessions: &Mutex<Sessions>, stats: &Mutex<StatsStore>, now: bool) {
+    let session = sessions
+        .lock()
+        .expect("Couldn't lock the sessions")
+        .take_finished(now);
+    match session.map(|session| session.save()) {
+        Some(Ok(session)) => info!(
+            "<blue>[MIDI]</> Saved session {} ({} notes)",
+            session.name, session.notes
+        ),
+        Some(Err(e)) => error!("<red>[MIDI]</> Could not save session: {}", e),
+        None => {}
+    }
+    let stats = stats
+        .lock()
+        .expect("Couldn't lock the stats")
+        .take_finished(now);
+    if let Some(Err(e)) = stats.map(|stats| stats.save()) {
+        error!("<red>[MIDI]</> Could not save stats: {}", e);
+    }
+}
+/// Forwards the notes of the device and nothing else, nothing being recorded
+pub fn forward_notes(input_port: &pm::InputPort, tx: &Sender<NoteEvent>, config: &Config) {
+    loop {
+        if let Ok(Some(events)) = input_port.read_n(config.midi.max_keys_processing) {
+            for event in events {
+                let event_type = get_midi_event_type(event.message.status, event.message.data2);
+                if matches!(event_type, MidiEventType::NoteOn | MidiEventType::NoteOff) {
+                    tx.send(NoteEvent::from_device(event_type, event))
+                        .expect("Failed to send MIDI event");
+                }
+            }
+        }
+        thread::sleep(time::Duration::from_millis(config.midi.timeout));
+    }
+}
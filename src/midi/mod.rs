@@ -1,2 +1,4 @@
 pub mod functions;
+pub mod recorder;
 pub mod rtp;
+pub mod sessions;
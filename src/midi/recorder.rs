@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use midly::{
+    live::LiveEvent, num::u28, Format, Header, MetaMessage, Smf, Timing, TrackEvent, TrackEventKind,
+};
+use portmidi::MidiEvent;
+use rocket::serde::Serialize;
+
+use crate::structs::Config;
+
+/// Resolution of the written files, in ticks per quarter note
+pub const TICKS_PER_BEAT: u16 = 480;
+/// Tempo of the written files, in microseconds per quarter note (120 BPM)
+pub const TEMPO: u32 = 500_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordedEvent {
+    /// Milliseconds since the first event of the take
+    pub time: u32,
+    pub message: [u8; 3],
+}
+impl RecordedEvent {
+    pub fn new(event: &MidiEvent, first_timestamp: u32) -> RecordedEvent {
+        RecordedEvent {
+            time: event.timestamp.saturating_sub(first_timestamp),
+            message: [
+                event.message.status,
+                event.message.data1,
+                event.message.data2,
+            ],
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct Take {
+    pub name: String,
+    pub size: u64,
+    pub created: u64,
+}
+
+/// Captures the live event stream coming from `watch_midi` and writes it
+/// to format-1 Standard MIDI Files.
+pub struct Recorder {
+    pub directory: PathBuf,
+    pub recording: bool,
+    events: Vec<RecordedEvent>,
+    first_timestamp: Option<u32>,
+}
+impl Recorder {
+    pub fn new(config: &Config) -> Recorder {
+        Recorder {
+            directory: PathBuf::from(&config.recorder.directory),
+            recording: false,
+            events: Vec::new(),
+            first_timestamp: None,
+        }
+    }
+    pub fn start(&mut self) {
+        self.events.clear();
+        self.first_timestamp = None;
+        self.recording = true;
+    }
+    /// Stops the current take and saves it, returning its file name.
+    /// Nothing is written if no event was captured.
+    pub fn stop(&mut self) -> std::io::Result<Option<String>> {
+        self.recording = false;
+        if self.events.is_empty() {
+            return Ok(None);
+        }
+        let events = std::mem::take(&mut self.events);
+        let name = format!("take-{}.mid", unix_time());
+        save_smf(&events, &self.directory.join(&name))?;
+        Ok(Some(name))
+    }
+    pub fn record(&mut self, event: &MidiEvent) {
+        if !self.recording {
+            return;
+        }
+        let first = *self.first_timestamp.get_or_insert(event.timestamp);
+        self.events.push(RecordedEvent::new(event, first));
+    }
+    pub fn list(&self) -> Vec<Take> {
+        let mut takes: Vec<Take> = fs::read_dir(&self.directory)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "mid"))
+                    .filter_map(|entry| {
+                        let metadata = entry.metadata().ok()?;
+                        Some(Take {
+                            name: entry.file_name().to_string_lossy().to_string(),
+                            size: metadata.len(),
+                            created: metadata
+                                .modified()
+                                .ok()?
+                                .duration_since(UNIX_EPOCH)
+                                .ok()?
+                                .as_secs(),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        takes.sort_by(|a, b| a.name.cmp(&b.name));
+        takes
+    }
+    /// Resolves a take name to its path, refusing anything that isn't a plain
+    /// file name inside the recordings directory.
+    pub fn path(&self, name: &str) -> Option<PathBuf> {
+        let file_name = Path::new(name).file_name()?;
+        if file_name != name {
+            return None;
+        }
+        let path = self.directory.join(file_name);
+        path.is_file().then_some(path)
+    }
+    pub fn delete(&self, name: &str) -> std::io::Result<bool> {
+        match self.path(name) {
+            Some(path) => fs::remove_file(path).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn ms_to_ticks(ms: u32) -> u64 {
+    ms as u64 * 1000 * TICKS_PER_BEAT as u64 / TEMPO as u64
+}
+
+/// Writes the events as a format-1 file: track 0 holds the tempo map, track 1
+/// the performance.
+pub fn save_smf(events: &[RecordedEvent], path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tempo_track = vec![
+        TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::TrackName(b"Tempo")),
+        },
+        TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::Tempo(TEMPO.into())),
+        },
+        TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, 24, 8)),
+        },
+        TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        },
+    ];
+    let mut piano_track = vec![TrackEvent {
+        delta: 0.into(),
+        kind: TrackEventKind::Meta(MetaMessage::TrackName(b"Piano")),
+    }];
+    let mut last_tick = 0;
+    for event in events.iter() {
+        let kind = match LiveEvent::parse(&event.message) {
+            Ok(LiveEvent::Midi { channel, message }) => TrackEventKind::Midi { channel, message },
+            _ => continue,
+        };
+        let tick = ms_to_ticks(event.time);
+        piano_track.push(TrackEvent {
+            delta: u28::new(tick.saturating_sub(last_tick) as u32),
+            kind,
+        });
+        last_tick = last_tick.max(tick);
+    }
+    piano_track.push(TrackEvent {
+        delta: 0.into(),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(TICKS_PER_BEAT.into()),
+    ));
+    smf.tracks.push(tempo_track);
+    smf.tracks.push(piano_track);
+    smf.save(path)
+}
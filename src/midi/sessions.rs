@@ -0,0 +1,189 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use portmidi::MidiEvent;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::functions::write_atomically;
+use crate::midi::functions::get_midi_event_type;
+use crate::midi::recorder::{save_smf, unix_time, RecordedEvent};
+use crate::structs::{Config, MidiEventType};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionInfo {
+    pub name: String,
+    /// Unix time of the first note
+    pub start: u64,
+    /// Unix time of the last event
+    pub end: u64,
+    pub notes: usize,
+    pub size: u64,
+}
+
+struct Session {
+    start: u64,
+    first_timestamp: u32,
+    last_event: Instant,
+    notes: usize,
+    events: Vec<RecordedEvent>,
+}
+
+/// Where the sessions are saved and how long they are kept
+#[derive(Debug, Clone)]
+pub struct SessionFiles {
+    pub directory: PathBuf,
+    /// Maximum age of a session in days, 0 to keep them forever
+    pub max_age: u64,
+    /// Maximum total size of the sessions in megabytes, 0 for no limit
+    pub max_size: u64,
+}
+impl SessionFiles {
+    /// Lists the saved sessions, oldest first.
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = fs::read_dir(&self.directory)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+                    .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+                    .filter_map(|data| serde_json::from_str(&data).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        sessions.sort_by_key(|session| session.start);
+        sessions
+    }
+    pub fn delete(&self, session: &SessionInfo) -> std::io::Result<()> {
+        fs::remove_file(self.directory.join(format!("{}.mid", session.name)))?;
+        fs::remove_file(self.directory.join(format!("{}.json", session.name)))
+    }
+    /// Removes the sessions older than `max_age`, then the oldest ones until
+    /// the total size fits in `max_size`.
+    pub fn prune(&self) -> std::io::Result<()> {
+        let mut sessions = self.list();
+        if self.max_age > 0 {
+            let oldest = unix_time().saturating_sub(self.max_age * 24 * 60 * 60);
+            for session in sessions.iter().filter(|session| session.end < oldest) {
+                self.delete(session)?;
+            }
+            sessions.retain(|session| session.end >= oldest);
+        }
+        if self.max_size > 0 {
+            let mut total: u64 = sessions.iter().map(|session| session.size).sum();
+            for session in sessions.iter() {
+                if total <= self.max_size * 1024 * 1024 {
+                    break;
+                }
+                self.delete(session)?;
+                total -= session.size;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A session that is over, to be written without holding the `Sessions`
+pub struct FinishedSession {
+    files: SessionFiles,
+    session: Session,
+    /// Unix time of the last event
+    end: u64,
+}
+impl FinishedSession {
+    /// Writes the session, then prunes the old ones. Returns what was
+    /// written.
+    pub fn save(self) -> std::io::Result<SessionInfo> {
+        let session = self.session;
+        let name = format!("session-{}", session.start);
+        let path = self.files.directory.join(format!("{}.mid", name));
+        save_smf(&session.events, &path)?;
+        let info = SessionInfo {
+            name: name.clone(),
+            start: session.start,
+            end: self.end,
+            notes: session.notes,
+            size: fs::metadata(&path)?.len(),
+        };
+        write_atomically(
+            &self.files.directory.join(format!("{}.json", name)),
+            &serde_json::to_string_pretty(&info)?,
+        )?;
+        self.files.prune()?;
+        Ok(info)
+    }
+}
+
+/// Always-on capture of everything that is played. A session starts with the
+/// first note after an idle gap and is saved once the piano has been silent
+/// for `idle_timeout` seconds.
+pub struct Sessions {
+    pub files: SessionFiles,
+    pub idle_timeout: Duration,
+    current: Option<Session>,
+}
+impl Sessions {
+    pub fn new(config: &Config) -> Sessions {
+        Sessions {
+            files: SessionFiles {
+                directory: PathBuf::from(&config.recorder.sessions.directory),
+                max_age: config.recorder.sessions.max_age,
+                max_size: config.recorder.sessions.max_size,
+            },
+            idle_timeout: Duration::from_secs(config.recorder.sessions.idle_timeout),
+            current: None,
+        }
+    }
+    pub fn record(&mut self, event: &MidiEvent) {
+        let is_note_on = matches!(
+            get_midi_event_type(event.message.status, event.message.data2),
+            MidiEventType::NoteOn
+        );
+        if self.current.is_none() {
+            if !is_note_on {
+                return;
+            }
+            self.current = Some(Session {
+                start: unix_time(),
+                first_timestamp: event.timestamp,
+                last_event: Instant::now(),
+                notes: 0,
+                events: Vec::new(),
+            });
+        }
+        if let Some(session) = self.current.as_mut() {
+            session
+                .events
+                .push(RecordedEvent::new(event, session.first_timestamp));
+            session.last_event = Instant::now();
+            if is_note_on {
+                session.notes += 1;
+            }
+        }
+    }
+    /// Takes the running session out once it has been idle long enough, or
+    /// right away when `now`, like on shutdown
+    pub fn take_finished(&mut self, now: bool) -> Option<FinishedSession> {
+        match &self.current {
+            Some(session) if now || session.last_event.elapsed() >= self.idle_timeout => {}
+            _ => return None,
+        }
+        let session = self.current.take().expect("Session should be running");
+        Some(FinishedSession {
+            files: self.files.clone(),
+            end: unix_time().saturating_sub(session.last_event.elapsed().as_secs()),
+            session,
+        })
+    }
+    /// Lists the saved sessions, oldest first.
+    pub fn list(&self) -> Vec<SessionInfo> {
+        self.files.list()
+    }
+    /// Resolves a session name to the path of its MIDI file.
+    pub fn path(&self, name: &str) -> Option<PathBuf> {
+        self.list()
+            .into_iter()
+            .find(|session| session.name == name)
+            .map(|session| self.files.directory.join(format!("{}.mid", session.name)))
+    }
+}
@@ -0,0 +1,237 @@
+use std::fmt;
+use std::fs;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use paris::{error, info, warn};
+use rocket::serde::Serialize;
+
+use crate::config::{check_config, diff_configs, load_config};
+use crate::harmony::Harmony;
+use crate::leds::state::LedHandle;
+use crate::midi::recorder::unix_time;
+use crate::settings::{remove_settings, save_settings, Settings};
+use crate::structs::{Config, Paths};
+
+/// How often the config file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Sections only read at startup
+const RESTART_REQUIRED: [&str; 4] = ["api", "recorder", "stats", "midi.rtp"];
+
+/// Outcome of the last reload of the config file
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(crate = "rocket::serde")]
+pub struct ReloadStatus {
+    /// Unix time of the reload, 0 before the first one
+    pub time: u64,
+    /// Keys whose change was applied
+    pub applied: Vec<String>,
+    /// Keys whose change only takes effect after a restart
+    pub restart_required: Vec<String>,
+    /// Problems of the config file, which was then ignored, or of the strip
+    /// it asked for, the LED settings being ignored then
+    pub errors: Vec<String>,
+}
+
+/// Why the settings couldn't be changed
+#[derive(Debug)]
+pub enum SettingsError {
+    /// Problems of the config they would give
+    Invalid(Vec<String>),
+    Save(String),
+}
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Invalid(errors) => write!(f, "{}", errors.join(", ")),
+            SettingsError::Save(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl From<SettingsError> for String {
+    fn from(e: SettingsError) -> String {
+        e.to_string()
+    }
+}
+
+fn get_modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Applies the changes of the config file to the running threads, each one
+/// applying its part. The strip and the MIDI device are reopened when their
+/// settings change. The settings changed through the API are applied over
+/// the file.
+pub struct Reloader {
+    paths: Paths,
+    /// Config as read from the file
+    file: Config,
+    /// Config with the settings applied
+    config: Config,
+    settings: Settings,
+    modified: Option<SystemTime>,
+    leds: LedHandle,
+    midi: Sender<Config>,
+    harmony: Arc<Mutex<Harmony>>,
+    pub status: ReloadStatus,
+}
+impl Reloader {
+    pub fn new(
+        paths: &Paths,
+        file: &Config,
+        settings: Settings,
+        leds: LedHandle,
+        midi: Sender<Config>,
+        harmony: &Arc<Mutex<Harmony>>,
+    ) -> Reloader {
+        Reloader {
+            paths: paths.clone(),
+            file: file.clone(),
+            config: settings.apply(file),
+            settings,
+            modified: get_modified_time(&paths.config),
+            leds,
+            midi,
+            harmony: harmony.clone(),
+            status: ReloadStatus::default(),
+        }
+    }
+    /// Config currently applied
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+    pub fn paths(&self) -> &Paths {
+        &self.paths
+    }
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+    pub fn is_modified(&self) -> bool {
+        get_modified_time(&self.paths.config) != self.modified
+    }
+    /// Reads the config file again and applies what changed, keeping the
+    /// current config if the file is invalid
+    pub fn reload(&mut self) -> ReloadStatus {
+        self.modified = get_modified_time(&self.paths.config);
+        let mut status = ReloadStatus {
+            time: unix_time(),
+            ..ReloadStatus::default()
+        };
+        let file = match load_config(&self.paths.config) {
+            Ok(file) => file,
+            Err(errors) => {
+                for e in errors.iter() {
+                    error!("<red>[Config]</> {}: {}", self.paths.config, e);
+                }
+                warn!("<yellow>[Config]</> Keeping the current config");
+                status.errors = errors.iter().map(|e| e.to_string()).collect();
+                self.status = status.clone();
+                return status;
+            }
+        };
+
+        let mut file = file;
+        for key in diff_configs(&self.file, &file) {
+            if RESTART_REQUIRED
+                .iter()
+                .any(|section| key == *section || key.starts_with(&format!("{}.", section)))
+            {
+                warn!("<yellow>[Config]</> `{}` changed, restart to apply it", key);
+                status.restart_required.push(key);
+            } else {
+                info!("<blue>[Config]</> Applying `{}`", key);
+                status.applied.push(key);
+            }
+        }
+        let (old, new) = (&self.file.leds, &file.leds);
+        if (new.pin, new.channel, new.num_leds) != (old.pin, old.channel, old.num_leds) {
+            if let Err(e) = self.leds.reopen_strip(new) {
+                error!("<red>[Config]</> {}", e);
+                warn!("<yellow>[Config]</> Keeping the current LED settings");
+                // Tried again when the file changes next
+                file.leds = self.file.leds.clone();
+                status
+                    .applied
+                    .retain(|key| key != "leds" && !key.starts_with("leds."));
+                status.errors.push(e);
+            }
+        }
+        if self.settings.forget(&status.applied) {
+            info!("<blue>[Settings]</> Dropping the settings changed in the config file");
+            if let Err(e) = save_settings(&self.paths.settings, &self.settings) {
+                error!("<red>[Settings]</> {}", e);
+            }
+        }
+        self.file = file;
+        self.apply(self.settings.apply(&self.file));
+        self.status = status.clone();
+        status
+    }
+    /// Changes the settings, saves them and applies them. Nothing changes if
+    /// they are invalid or can't be saved.
+    pub fn update_settings<F>(&mut self, change: F) -> Result<(), SettingsError>
+    where
+        F: FnOnce(&mut Settings),
+    {
+        let mut settings = self.settings.clone();
+        change(&mut settings);
+        let errors = check_config(&settings.apply(&self.file));
+        if !errors.is_empty() {
+            let errors = errors.iter().map(|e| e.to_string()).collect();
+            return Err(SettingsError::Invalid(errors));
+        }
+        save_settings(&self.paths.settings, &settings).map_err(SettingsError::Save)?;
+        self.settings = settings;
+        self.apply(self.settings.apply(&self.file));
+        Ok(())
+    }
+    /// Forgets every setting changed through the API, going back to the
+    /// config file
+    pub fn reset_settings(&mut self) -> Result<(), String> {
+        remove_settings(&self.paths.settings)?;
+        self.settings = Settings::default();
+        self.apply(self.file.clone());
+        Ok(())
+    }
+    /// Sends what changed in `config` to the threads using it
+    fn apply(&mut self, config: Config) {
+        if config.leds != self.config.leds {
+            let applied = config.clone();
+            self.leds.send(move |leds| leds.apply_config(&applied));
+        }
+        if config.midi != self.config.midi && self.midi.send(config.clone()).is_err() {
+            error!("<red>[Config]</> The MIDI thread stopped");
+        }
+        if config.harmony != self.config.harmony {
+            self.harmony
+                .lock()
+                .expect("Couldn't lock the harmony")
+                .window = Duration::from_secs(config.harmony.window);
+        }
+        self.config = config;
+    }
+}
+
+/// Reloads the config when its file changes, or when asked through
+/// `requests`
+pub fn watch_config(reloader: &Arc<Mutex<Reloader>>, requests: &Receiver<()>) {
+    loop {
+        let requested = match requests.recv_timeout(POLL_INTERVAL) {
+            Ok(()) => true,
+            Err(RecvTimeoutError::Timeout) => false,
+            // Only polling the file is left
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(POLL_INTERVAL);
+                false
+            }
+        };
+        let mut reloader = reloader.lock().expect("Couldn't lock the reloader");
+        if requested || reloader.is_modified() {
+            reloader.reload();
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use paris::warn;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::animations::compositor::BlendMode;
+use crate::animations::get_parameter_schema;
+use crate::functions::{load_json_store, write_atomically};
+use crate::leds::state::{LedHandle, LedState};
+use crate::reload::Reloader;
+use crate::structs::Animator;
+
+/// A layer of a scene
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SceneLayer {
+    pub animation: String,
+    pub opacity: f32,
+    pub blend: BlendMode,
+    /// The layer set through `/api/animation`
+    pub primary: bool,
+    pub parameters: HashMap<String, f32>,
+}
+
+/// A look of the strip saved under a name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Scene {
+    pub name: String,
+    /// MIDI program, from 0, recalling the scene on a program change
+    #[serde(default)]
+    pub program: Option<u8>,
+    pub color_mode: String,
+    pub brightness: u8,
+    /// Layers from bottom to top, the primary one included
+    pub layers: Vec<SceneLayer>,
+}
+impl Scene {
+    /// Captures the current look of the strip
+    pub fn capture(name: String, program: Option<u8>, leds: &LedState) -> Scene {
+        let animator = &leds.animator;
+        let layers = animator
+            .layers
+            .iter()
+            .map(|layer| {
+                let primary = layer.id == 0;
+                // The ambient animation only stands in for the primary one,
+                // which comes back with the parameters of the config
+                let (animation, parameters) = match (&animator.idle.performance, primary) {
+                    (Some(performance), true) => (
+                        performance.to_string(),
+                        animator.initial_parameters(performance),
+                    ),
+                    _ => (
+                        layer.animation.to_string(),
+                        layer.animator.parameters().clone(),
+                    ),
+                };
+                SceneLayer {
+                    animation,
+                    opacity: layer.opacity,
+                    blend: layer.blend,
+                    primary,
+                    parameters: parameters
+                        .0
+                        .iter()
+                        .map(|parameter| (parameter.schema.name.to_string(), parameter.value))
+                        .collect(),
+                }
+            })
+            .collect();
+        Scene {
+            name,
+            program,
+            color_mode: leds.color_mode.mode.to_string(),
+            brightness: leds.config.brightness,
+            layers,
+        }
+    }
+    fn primary(&self) -> Option<&SceneLayer> {
+        self.layers.iter().find(|layer| layer.primary)
+    }
+}
+
+/// Checks that every layer of a scene can be restored, before any is changed
+fn check_layers(animator: &Animator, layers: &[SceneLayer]) -> Result<(), String> {
+    for layer in layers {
+        if get_parameter_schema(&layer.animation).is_none() {
+            return Err(format!("Unknown animation `{}`", layer.animation));
+        }
+        animator
+            .initial_parameters(&layer.animation)
+            .set_all(&layer.parameters)
+            .map_err(|errors| errors.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Replaces the layers on top of the primary one with those of a scene,
+/// waking the primary layer up from the ambient animation. Nothing changes
+/// if a layer can't be restored.
+fn restore_layers(animator: &mut Animator, layers: &[SceneLayer]) -> Result<(), String> {
+    check_layers(animator, layers)?;
+    // The primary layer holds the ambient animation while idle
+    let performance = animator.idle.wake();
+    let primary = layers.iter().find(|layer| layer.primary);
+    match (primary, performance) {
+        (Some(primary), performance)
+            if performance.is_some() || animator.animation() != primary.animation =>
+        {
+            animator.set_animation(primary.animation.to_string())?
+        }
+        (None, Some(performance)) => animator.set_animation(performance)?,
+        _ => {}
+    }
+    let ids: Vec<usize> = animator
+        .layers
+        .iter()
+        .map(|layer| layer.id)
+        .filter(|id| *id != 0)
+        .collect();
+    for id in ids {
+        animator.remove_layer(id)?;
+    }
+    for (index, scene_layer) in layers.iter().enumerate() {
+        let id = if scene_layer.primary {
+            0
+        } else {
+            animator.add_layer(
+                scene_layer.animation.to_string(),
+                scene_layer.opacity,
+                scene_layer.blend,
+            )?
+        };
+        let layer = animator
+            .layer_mut(id)
+            .ok_or_else(|| format!("Unknown layer {}", id))?;
+        layer.opacity = scene_layer.opacity.clamp(0.0, 1.0);
+        layer.blend = scene_layer.blend;
+        layer
+            .animator
+            .parameters_mut()
+            .set_all(&scene_layer.parameters)
+            .map_err(|errors| errors.join(", "))?;
+        animator.move_layer(id, index)?;
+    }
+    Ok(())
+}
+
+/// Applies a scene. Its color mode, brightness and primary animation are
+/// saved with the settings, its other layers only last until the next
+/// restart like those added through the API.
+pub fn apply_scene(
+    scene: &Scene,
+    reloader: &Mutex<Reloader>,
+    leds: &LedHandle,
+) -> Result<(), String> {
+    let primary = scene
+        .primary()
+        .ok_or_else(|| format!("Scene `{}` has no primary layer", scene.name))?
+        .clone();
+    reloader
+        .lock()
+        .expect("Couldn't lock the reloader")
+        .update_settings(|settings| {
+            settings.color_mode = Some(scene.color_mode.to_string());
+            settings.brightness = Some(scene.brightness);
+            settings.animation = Some(primary.animation.to_string());
+            settings
+                .animations
+                .insert(primary.animation.to_string(), primary.parameters.clone());
+        })?;
+    let (name, layers) = (scene.name.to_string(), scene.layers.clone());
+    leds.send(move |leds| {
+        if let Err(e) = restore_layers(&mut leds.animator, &layers) {
+            warn!(
+                "<yellow>[Scenes]</> Couldn't restore the layers of `{}`: {}",
+                name, e
+            );
+        }
+    });
+    Ok(())
+}
+
+/// File-based store of the scenes
+pub struct SceneStore {
+    pub path: PathBuf,
+    pub scenes: Vec<Scene>,
+}
+impl SceneStore {
+    pub fn new(path: &str) -> SceneStore {
+        let path = PathBuf::from(path);
+        let scenes = load_json_store(&path).unwrap_or_else(|e| {
+            warn!("<yellow>[Scenes]</> {}, starting without scenes", e);
+            Vec::new()
+        });
+        SceneStore { path, scenes }
+    }
+    pub fn get(&self, name: &str) -> Option<&Scene> {
+        self.scenes.iter().find(|scene| scene.name == name)
+    }
+    /// Scene recalled by a MIDI program change
+    pub fn by_program(&self, program: u8) -> Option<&Scene> {
+        self.scenes
+            .iter()
+            .find(|scene| scene.program == Some(program))
+    }
+    /// Adds a scene, replacing the one with the same name
+    pub fn insert(&mut self, scene: Scene) -> Result<(), String> {
+        if scene.name.trim().is_empty() {
+            return Err("The scene needs a name".to_string());
+        }
+        if let Some(program) = scene.program {
+            if let Some(other) = self
+                .by_program(program)
+                .filter(|other| other.name != scene.name)
+            {
+                return Err(format!(
+                    "Program {} already recalls scene `{}`",
+                    program, other.name
+                ));
+            }
+        }
+        let mut scenes = self.scenes.clone();
+        match scenes.iter_mut().find(|other| other.name == scene.name) {
+            Some(other) => *other = scene,
+            None => scenes.push(scene),
+        }
+        self.save(&scenes)?;
+        self.scenes = scenes;
+        Ok(())
+    }
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        let mut scenes = self.scenes.clone();
+        let index = scenes
+            .iter()
+            .position(|scene| scene.name == name)
+            .ok_or_else(|| format!("Unknown scene `{}`", name))?;
+        scenes.remove(index);
+        self.save(&scenes)?;
+        self.scenes = scenes;
+        Ok(())
+    }
+    fn save(&self, scenes: &[Scene]) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(scenes)
+            .map_err(|e| format!("Couldn't serialize the scenes: {}", e))?;
+        write_atomically(&self.path, &data)
+            .map_err(|e| format!("Couldn't write {}: {}", self.path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::structs::Config;
+
+    fn animator() -> Animator {
+        let config: Config =
+            toml::from_str(include_str!("../config.toml")).expect("config.toml should be valid");
+        Animator::new(&config, &"ripple".to_string())
+    }
+    fn layer(animation: &str, primary: bool, parameters: &[(&str, f32)]) -> SceneLayer {
+        SceneLayer {
+            animation: animation.to_string(),
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+            primary,
+            parameters: parameters
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect(),
+        }
+    }
+    fn animations(animator: &Animator) -> Vec<&str> {
+        animator
+            .layers
+            .iter()
+            .map(|layer| layer.animation.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn restores_the_layers_in_order() {
+        let mut animator = animator();
+        animator
+            .add_layer("sparkle".to_string(), 1.0, BlendMode::Normal)
+            .unwrap();
+        let layers = [
+            layer("fire", false, &[]),
+            layer("ripple", true, &[("speed", 30.0)]),
+        ];
+        restore_layers(&mut animator, &layers).unwrap();
+        assert_eq!(animations(&animator), vec!["fire", "ripple"]);
+        assert_eq!(
+            animator
+                .primary()
+                .unwrap()
+                .animator
+                .parameters()
+                .get("speed"),
+            30.0
+        );
+    }
+
+    #[test]
+    fn wakes_up_to_restore_the_primary_layer() {
+        let mut animator = animator();
+        animator.idle.timeout = Duration::from_secs(1);
+        animator.idle.last_note -= Duration::from_secs(2);
+        animator.update(0.0);
+        assert!(animator.idle.is_idle());
+        assert_eq!(animator.animation(), "aurora");
+
+        let layers = [
+            layer("ripple", true, &[("speed", 30.0)]),
+            layer("fire", false, &[]),
+        ];
+        restore_layers(&mut animator, &layers).unwrap();
+        assert!(!animator.idle.is_idle());
+        assert_eq!(animations(&animator), vec!["ripple", "fire"]);
+        assert_eq!(
+            animator
+                .primary()
+                .unwrap()
+                .animator
+                .parameters()
+                .get("speed"),
+            30.0
+        );
+    }
+
+    #[test]
+    fn leaves_the_layers_as_they_were_when_one_is_invalid() {
+        let mut animator = animator();
+        animator
+            .add_layer("sparkle".to_string(), 1.0, BlendMode::Normal)
+            .unwrap();
+        let layers = [
+            layer("fire", true, &[]),
+            layer("ripple", false, &[("what", 1.0)]),
+        ];
+        assert!(restore_layers(&mut animator, &layers).is_err());
+        assert_eq!(animations(&animator), vec!["ripple", "sparkle"]);
+
+        let layers = [layer("fire", true, &[]), layer("nothing", false, &[])];
+        assert_eq!(
+            restore_layers(&mut animator, &layers),
+            Err("Unknown animation `nothing`".to_string())
+        );
+        assert_eq!(animations(&animator), vec!["ripple", "sparkle"]);
+    }
+}
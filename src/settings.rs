@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use paris::warn;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::check_config;
+use crate::functions::write_atomically;
+use crate::structs::{Config, IdleConfig, TransitionConfig};
+
+/// Settings changed through the API. They are saved apart from the config
+/// file, which is left as written by hand, and applied over it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Settings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    /// Animation of the primary layer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition: Option<TransitionConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle: Option<IdleConfig>,
+    /// Parameters of each animation, by animation name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub animations: HashMap<String, HashMap<String, f32>>,
+}
+impl Settings {
+    /// `config` with these settings applied over it
+    pub fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        let leds = &mut config.leds;
+        if let Some(color_mode) = &self.color_mode {
+            leds.color_mode = color_mode.to_string();
+        }
+        if let Some(brightness) = self.brightness {
+            leds.brightness = brightness;
+        }
+        if let Some(animation) = &self.animation {
+            leds.animation = animation.to_string();
+        }
+        if let Some(transition) = &self.transition {
+            leds.transition = transition.clone();
+        }
+        if let Some(idle) = &self.idle {
+            leds.idle = idle.clone();
+        }
+        for (animation, values) in self.animations.iter() {
+            leds.animations
+                .entry(animation.to_string())
+                .or_default()
+                .extend(
+                    values
+                        .iter()
+                        .map(|(name, value)| (name.to_string(), *value)),
+                );
+        }
+        config
+    }
+    /// Drops the settings whose key was changed in the config file since,
+    /// the latest change winning. Returns whether any was dropped.
+    pub fn forget(&mut self, keys: &[String]) -> bool {
+        let before = self.clone();
+        for key in keys {
+            let mut path = key.split('.');
+            if path.next() != Some("leds") {
+                continue;
+            }
+            match (path.next(), path.next()) {
+                (None, _) => *self = Settings::default(),
+                (Some("color_mode"), _) => self.color_mode = None,
+                (Some("brightness"), _) => self.brightness = None,
+                (Some("animation"), _) => self.animation = None,
+                (Some("transition"), _) => self.transition = None,
+                (Some("idle"), _) => self.idle = None,
+                (Some("animations"), Some(animation)) => {
+                    self.animations.remove(animation);
+                }
+                (Some("animations"), None) => self.animations.clear(),
+                _ => {}
+            }
+        }
+        *self != before
+    }
+}
+
+/// Reads the saved settings. They are ignored when they can't be read or
+/// when applying them to `config` would make it invalid, after an update
+/// removed an animation for instance.
+pub fn load_settings(path: &str, config: &Config) -> Settings {
+    let settings: Settings = match fs::read_to_string(path) {
+        Ok(data) => match serde_json::from_str(&data) {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!("<yellow>[Settings]</> Ignoring {}: {}", path, e);
+                return Settings::default();
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => return Settings::default(),
+        Err(e) => {
+            warn!("<yellow>[Settings]</> Couldn't read {}: {}", path, e);
+            return Settings::default();
+        }
+    };
+    let errors = check_config(&settings.apply(config));
+    if !errors.is_empty() {
+        for e in errors.iter() {
+            warn!("<yellow>[Settings]</> {}: {}", path, e);
+        }
+        warn!("<yellow>[Settings]</> Ignoring {}", path);
+        return Settings::default();
+    }
+    settings
+}
+pub fn save_settings(path: &str, settings: &Settings) -> Result<(), String> {
+    let data = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Couldn't serialize the settings: {}", e))?;
+    write_atomically(Path::new(path), &data).map_err(|e| format!("Couldn't write {}: {}", path, e))
+}
+/// Removes the saved settings, the config file applying alone again
+pub fn remove_settings(path: &str) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            Err(format!("Couldn't remove {}: {}", path, e))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+
+use rocket::serde::Serialize;
+
+use super::{SessionStats, VELOCITY_BUCKETS};
+
+/// Share of all hits the most-used range has to cover
+const RANGE_COVERAGE: f32 = 0.9;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct DayStats {
+    /// Unix time of the start of the day (UTC)
+    pub day: u64,
+    pub sessions: usize,
+    pub playing_time: u64,
+    pub notes: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct StatsReport {
+    pub from: u64,
+    pub to: u64,
+    pub sessions: usize,
+    /// Seconds spent playing
+    pub playing_time: u64,
+    pub notes: u32,
+    pub key_hits: Vec<u32>,
+    pub velocities: Vec<u32>,
+    /// Narrowest range of notes covering 90% of the hits
+    pub range: Option<(u8, u8)>,
+    /// Consecutive days played, up to `to`
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub days: Vec<DayStats>,
+}
+
+pub fn get_report(sessions: &[SessionStats], from: u64, to: u64) -> StatsReport {
+    let mut key_hits = vec![0; 128];
+    let mut velocities = vec![0; VELOCITY_BUCKETS];
+    let mut days: BTreeMap<u64, DayStats> = BTreeMap::new();
+    for session in sessions.iter() {
+        for (total, hits) in key_hits.iter_mut().zip(session.key_hits.iter()) {
+            *total += hits;
+        }
+        for (total, hits) in velocities.iter_mut().zip(session.velocities.iter()) {
+            *total += hits;
+        }
+        let day = session.start - session.start % SECONDS_PER_DAY;
+        let day_stats = days.entry(day).or_insert(DayStats {
+            day,
+            sessions: 0,
+            playing_time: 0,
+            notes: 0,
+        });
+        day_stats.sessions += 1;
+        day_stats.playing_time += session.duration();
+        day_stats.notes += session.notes;
+    }
+    let (current_streak, longest_streak) = get_streaks(days.keys().copied(), to);
+    StatsReport {
+        from,
+        to,
+        sessions: sessions.len(),
+        playing_time: sessions.iter().map(|session| session.duration()).sum(),
+        notes: sessions.iter().map(|session| session.notes).sum(),
+        range: get_most_used_range(&key_hits),
+        key_hits,
+        velocities,
+        current_streak,
+        longest_streak,
+        days: days.into_values().collect(),
+    }
+}
+
+/// Finds the narrowest window of notes holding at least `RANGE_COVERAGE` of
+/// the hits.
+pub fn get_most_used_range(key_hits: &[u32]) -> Option<(u8, u8)> {
+    let total: u32 = key_hits.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let needed = (total as f32 * RANGE_COVERAGE).ceil() as u32;
+    let mut best: Option<(usize, usize)> = None;
+    let mut low = 0;
+    let mut covered = 0;
+    for high in 0..key_hits.len() {
+        covered += key_hits[high];
+        while covered - key_hits[low] >= needed {
+            covered -= key_hits[low];
+            low += 1;
+        }
+        if covered >= needed && best.is_none_or(|(l, h)| high - low < h - l) {
+            best = Some((low, high));
+        }
+    }
+    best.map(|(low, high)| (low as u8, high as u8))
+}
+
+/// Returns the current streak (ending on the day of `to` or the day before)
+/// and the longest streak of consecutive days in `days`, which must be sorted.
+pub fn get_streaks(days: impl Iterator<Item = u64>, to: u64) -> (u32, u32) {
+    let today = to - to % SECONDS_PER_DAY;
+    let mut longest = 0;
+    let mut streak = 0;
+    let mut last_day: Option<u64> = None;
+    for day in days {
+        streak = match last_day {
+            Some(last) if day == last + SECONDS_PER_DAY => streak + 1,
+            _ => 1,
+        };
+        longest = longest.max(streak);
+        last_day = Some(day);
+    }
+    let current = match last_day {
+        Some(last) if last + SECONDS_PER_DAY >= today => streak,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Midnight UTC of some day
+    const DAY: u64 = 1_700_006_400;
+
+    fn days(offsets: &[u64]) -> impl Iterator<Item = u64> + '_ {
+        offsets.iter().map(|offset| DAY + offset * SECONDS_PER_DAY)
+    }
+    fn session(start: u64, duration: u64, notes: &[u8]) -> SessionStats {
+        let mut session = SessionStats::new(start);
+        session.end = start + duration;
+        for note in notes {
+            session.notes += 1;
+            session.key_hits[*note as usize] += 1;
+        }
+        session
+    }
+
+    #[test]
+    fn counts_consecutive_days() {
+        let to = DAY + 2 * SECONDS_PER_DAY + 3600;
+        assert_eq!(get_streaks(days(&[0, 1, 2]), to), (3, 3));
+        // Still going until the end of the next day
+        let to = DAY + 3 * SECONDS_PER_DAY + 3600;
+        assert_eq!(get_streaks(days(&[0, 1, 2]), to), (3, 3));
+    }
+
+    #[test]
+    fn restarts_the_streak_after_a_gap() {
+        let to = DAY + 5 * SECONDS_PER_DAY;
+        assert_eq!(get_streaks(days(&[0, 1, 2, 4, 5]), to), (2, 3));
+        let to = DAY + 8 * SECONDS_PER_DAY;
+        assert_eq!(get_streaks(days(&[0, 1, 2, 4, 5]), to), (0, 3));
+    }
+
+    #[test]
+    fn counts_a_single_day() {
+        assert_eq!(get_streaks(days(&[0]), DAY + 60), (1, 1));
+    }
+
+    #[test]
+    fn has_no_streak_without_sessions() {
+        assert_eq!(get_streaks(days(&[]), DAY), (0, 0));
+        let report = get_report(&[], DAY, DAY + SECONDS_PER_DAY);
+        assert_eq!(report.sessions, 0);
+        assert_eq!((report.current_streak, report.longest_streak), (0, 0));
+        assert_eq!(report.range, None);
+        assert!(report.days.is_empty());
+    }
+
+    #[test]
+    fn splits_the_sessions_by_their_day() {
+        let sessions = [
+            session(DAY - 60, 600, &[60]),
+            session(DAY + 60, 300, &[60, 62]),
+            session(DAY + 7200, 300, &[64]),
+        ];
+        let report = get_report(&sessions, DAY - SECONDS_PER_DAY, DAY + 7200);
+        let days: Vec<(u64, usize, u64, u32)> = report
+            .days
+            .iter()
+            .map(|day| (day.day, day.sessions, day.playing_time, day.notes))
+            .collect();
+        assert_eq!(
+            days,
+            vec![(DAY - SECONDS_PER_DAY, 1, 600, 1), (DAY, 2, 600, 3)]
+        );
+        assert_eq!((report.current_streak, report.longest_streak), (2, 2));
+        assert_eq!(report.notes, 4);
+    }
+
+    #[test]
+    fn finds_the_narrowest_range_covering_the_hits() {
+        let mut key_hits = vec![0; 128];
+        key_hits[21] = 1;
+        key_hits[60..=67].fill(10);
+        assert_eq!(get_most_used_range(&key_hits), Some((60, 67)));
+        assert_eq!(get_most_used_range(&[0; 128]), None);
+
+        let mut key_hits = vec![0; 128];
+        key_hits[60] = 5;
+        assert_eq!(get_most_used_range(&key_hits), Some((60, 60)));
+    }
+}
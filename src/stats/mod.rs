@@ -0,0 +1,138 @@
+pub mod functions;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use paris::warn;
+use portmidi::MidiEvent;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::functions::{load_json_store, write_atomically};
+use crate::midi::{functions::get_midi_event_type, recorder::unix_time};
+use crate::structs::{Config, MidiEventType};
+
+/// Number of buckets the 0-127 velocity range is split into
+pub const VELOCITY_BUCKETS: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionStats {
+    /// Unix time of the first note
+    pub start: u64,
+    /// Unix time of the last event
+    pub end: u64,
+    pub notes: u32,
+    /// Hits for each of the 128 MIDI notes
+    pub key_hits: Vec<u32>,
+    /// Hits for each velocity bucket, softest first
+    pub velocities: Vec<u32>,
+}
+impl SessionStats {
+    pub fn new(start: u64) -> SessionStats {
+        SessionStats {
+            start,
+            end: start,
+            notes: 0,
+            key_hits: vec![0; 128],
+            velocities: vec![0; VELOCITY_BUCKETS],
+        }
+    }
+    pub fn duration(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Stored sessions to write, without holding the `StatsStore`
+pub struct StatsFile {
+    path: PathBuf,
+    data: String,
+}
+impl StatsFile {
+    pub fn save(&self) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomically(&self.path, &self.data)
+    }
+}
+
+/// File-based store of per-session statistics, fed from the note stream.
+/// Sessions are split on the same idle timeout as the session capture.
+pub struct StatsStore {
+    pub path: PathBuf,
+    pub idle_timeout: Duration,
+    pub sessions: Vec<SessionStats>,
+    current: Option<SessionStats>,
+    last_event: Instant,
+}
+impl StatsStore {
+    pub fn new(config: &Config) -> StatsStore {
+        let path = PathBuf::from(&config.stats.path);
+        let sessions = load_json_store(&path).unwrap_or_else(|e| {
+            warn!("<yellow>[Stats]</> {}, starting empty", e);
+            Vec::new()
+        });
+        StatsStore {
+            path,
+            idle_timeout: Duration::from_secs(config.recorder.sessions.idle_timeout),
+            sessions,
+            current: None,
+            last_event: Instant::now(),
+        }
+    }
+    pub fn record(&mut self, event: &MidiEvent) {
+        if !matches!(
+            get_midi_event_type(event.message.status, event.message.data2),
+            MidiEventType::NoteOn
+        ) {
+            return;
+        }
+        let now = unix_time();
+        let session = self.current.get_or_insert_with(|| SessionStats::new(now));
+        session.end = now;
+        session.notes += 1;
+        session.key_hits[(event.message.data1 & 0x7f) as usize] += 1;
+        session.velocities[(event.message.data2 & 0x7f) as usize * VELOCITY_BUCKETS / 128] += 1;
+        self.last_event = Instant::now();
+    }
+    /// Stores the running session once it has been idle long enough, or
+    /// right away when `now` like on shutdown, returning the file to write
+    pub fn take_finished(&mut self, now: bool) -> Option<StatsFile> {
+        if self.current.is_none() || (!now && self.last_event.elapsed() < self.idle_timeout) {
+            return None;
+        }
+        let session = self.current.take().expect("Session should be running");
+        self.sessions.push(session);
+        Some(StatsFile {
+            path: self.path.clone(),
+            data: serde_json::to_string(&self.sessions).expect("The stats should serialize"),
+        })
+    }
+    /// Hits of each note over the sessions of the last `days` days, the one
+    /// being played included, or of the one being played only for 0 days
+    pub fn recent_key_hits(&self, days: u64) -> Vec<u32> {
+        let sessions = if days == 0 {
+            self.current.iter().cloned().collect()
+        } else {
+            let to = unix_time();
+            self.sessions_between(to.saturating_sub(days * 24 * 60 * 60), to)
+        };
+        let mut key_hits = vec![0; 128];
+        for session in sessions.iter() {
+            for (total, hits) in key_hits.iter_mut().zip(session.key_hits.iter()) {
+                *total += hits;
+            }
+        }
+        key_hits
+    }
+    /// Stored sessions that started between `from` and `to` (unix times,
+    /// inclusive), including the one being played.
+    pub fn sessions_between(&self, from: u64, to: u64) -> Vec<SessionStats> {
+        self.sessions
+            .iter()
+            .chain(self.current.iter())
+            .filter(|session| session.start >= from && session.start <= to)
+            .cloned()
+            .collect()
+    }
+}
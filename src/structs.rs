@@ -1,37 +1,135 @@
-use cichlid::{prelude::RainbowFillSingleCycle, ColorRGB};
+use cichlid::{ColorRGB, HSV};
+use paris::warn;
+use portmidi::MidiEvent;
 use rand::prelude::*;
-use rs_ws281x::Controller;
-use serde_derive::Deserialize;
+use rs_ws281x::RawColor;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
+use crate::animations::{
+    compositor::{composite, BlendMode, Layer},
+    create_animation, get_parameter_schema,
+    heatmap::HistoryRequest,
+    idle::Idle,
+    parameters::{ParameterSchema, Parameters},
+    transition::{Transition, TransitionKind},
+    Animation,
+};
 use crate::functions::hex_to_rgb;
+use crate::harmony::HarmonySnapshot;
+use crate::leds::mapping::{Key, KeyMap, MappingConfig};
+use crate::midi::functions::midi_time;
 
-#[derive(Deserialize, Debug, Clone)]
+/// Config file used when none is given
+pub const CONFIG_PATH: &str = "config.toml";
+/// Settings changed through the API, applied over the config file
+const SETTINGS_FILE: &str = "settings.json";
+/// Scenes saved through the API
+const SCENES_FILE: &str = "scenes.json";
+
+/// Files the state is read from and saved to
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// Read at startup, rewritten by the key calibration
+    pub config: String,
+    pub settings: String,
+    pub scenes: String,
+}
+impl Paths {
+    /// The settings and the scenes are saved next to the config file
+    pub fn new(config: &str) -> Paths {
+        let dir = Path::new(config).parent().unwrap_or(Path::new(""));
+        let beside = |file: &str| dir.join(file).to_string_lossy().to_string();
+        Paths {
+            config: config.to_string(),
+            settings: beside(SETTINGS_FILE),
+            scenes: beside(SCENES_FILE),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Config {
     pub leds: LedsConfig,
     pub midi: MidiConfig,
     pub api: ApiConfig,
+    pub recorder: RecorderConfig,
+    pub stats: StatsConfig,
+    pub harmony: HarmonyConfig,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ApiConfig {
     pub host: String,
     pub port: u16,
+    /// Build of the web UI, unused when it is embedded
+    #[serde(default = "default_web_root")]
+    pub web_root: String,
+}
+fn default_web_root() -> String {
+    "/home/pi/web/build".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecorderConfig {
+    pub directory: String,
+    pub sessions: SessionsConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionsConfig {
+    pub directory: String,
+    pub idle_timeout: u64,
+    pub max_age: u64,
+    pub max_size: u64,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatsConfig {
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HarmonyConfig {
+    /// Seconds of playing the key is estimated from
+    pub window: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct LedsConfig {
     pub pin: i32,
     pub num_leds: usize,
     pub brightness: u8,
-    pub offsets: Vec<Vec<u8>>,
-    pub shift: u8,
-    pub fade: i8,
     pub channel: usize,
     pub color_mode: String,
     pub animation: String,
+    /// Frames rendered each second
+    pub fps: u32,
+    /// Initial parameters of each animation, by animation name
+    #[serde(default)]
+    pub animations: HashMap<String, HashMap<String, f32>>,
+    pub transition: TransitionConfig,
+    pub idle: IdleConfig,
+    pub mapping: MappingConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TransitionConfig {
+    pub kind: TransitionKind,
+    /// Milliseconds
+    pub duration: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IdleConfig {
+    /// Seconds without notes before switching to `animation`, 0 to disable
+    pub timeout: u64,
+    pub animation: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct MidiConfig {
     pub id: i32,
     pub buffer_size: usize,
@@ -39,7 +137,7 @@ pub struct MidiConfig {
     pub timeout: u64,
     pub rtp: RtpConfig,
 }
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RtpConfig {
     pub socket: String,
 }
@@ -49,306 +147,366 @@ pub enum MidiEventType {
     NoteOn,
     NoteOff,
     ControlChange,
+    ProgramChange,
+}
+
+/// When a note happened, to measure how long it takes to be shown
+#[derive(Debug, Clone, Copy)]
+pub enum NoteTime {
+    /// Timestamp of an event of a MIDI device, in milliseconds of the
+    /// PortMidi clock
+    Device(u32),
+    /// Time a note that doesn't come from a device, like those of a MIDI
+    /// file, was sent at
+    Sent(Instant),
+}
+impl NoteTime {
+    pub fn elapsed(&self) -> Duration {
+        match self {
+            NoteTime::Device(timestamp) => {
+                Duration::from_millis(midi_time().saturating_sub(*timestamp) as u64)
+            }
+            NoteTime::Sent(sent) => sent.elapsed(),
+        }
+    }
 }
 
-pub enum AnimatorEnum {
-    Fades(Fades),
-    Ripples(Ripples),
-    Static(StaticColor),
-    Default(DefaultAnimator),
+/// A note sent to the LED thread, with the time it happened at to measure
+/// how long it takes to be shown
+#[derive(Debug)]
+pub struct NoteEvent {
+    pub event_type: MidiEventType,
+    pub event: MidiEvent,
+    pub time: NoteTime,
+}
+impl NoteEvent {
+    pub fn new(event_type: MidiEventType, event: MidiEvent) -> NoteEvent {
+        NoteEvent {
+            event_type,
+            event,
+            time: NoteTime::Sent(Instant::now()),
+        }
+    }
+    /// A note read from a MIDI device, timed by its timestamp
+    pub fn from_device(event_type: MidiEventType, event: MidiEvent) -> NoteEvent {
+        NoteEvent {
+            event_type,
+            event,
+            time: NoteTime::Device(event.timestamp),
+        }
+    }
 }
 
+/// Owns the stack of animation layers, dispatches the note events to all of
+/// them and composites their frames. The layer with id 0 is the primary
+/// animation, the one set through `/api/animation`.
 pub struct Animator {
-    pub animation: String,
-    pub animator: AnimatorEnum,
+    pub layers: Vec<Layer>,
+    /// Transition played when switching animation or color mode
+    pub transition: TransitionKind,
+    pub transition_duration: Duration,
+    pub idle: Idle,
+    color_mode: Option<ColorMode>,
+    next_id: usize,
     config: Config,
 }
 impl Animator {
+    /// Unknown animations fall back to `default`
     pub fn new(config: &Config, animation: &String) -> Self {
-        match animation.as_str() {
-            "fade" => Self {
-                config: config.clone(),
-                animation: animation.to_string(),
-                animator: AnimatorEnum::Fades(Fades::new(config)),
-            },
-            "ripple" => Self {
-                config: config.clone(),
-                animation: animation.to_string(),
-                animator: AnimatorEnum::Ripples(Ripples::new(config)),
-            },
-            "static" => Self {
-                config: config.clone(),
-                animation: animation.to_string(),
-                animator: AnimatorEnum::Static(StaticColor::new(config)),
-            },
-            _ => Self {
-                config: config.clone(),
-                animation: animation.to_string(),
-                animator: AnimatorEnum::Default(DefaultAnimator::new(config)),
-            },
+        let mut animator = Self {
+            layers: Vec::new(),
+            transition: config.leds.transition.kind,
+            transition_duration: Duration::from_millis(config.leds.transition.duration),
+            idle: Idle::new(config),
+            color_mode: None,
+            next_id: 0,
+            config: config.clone(),
+        };
+        if animator
+            .add_layer(animation.to_string(), 1.0, BlendMode::Normal)
+            .is_err()
+        {
+            animator
+                .add_layer("default".to_string(), 1.0, BlendMode::Normal)
+                .expect("No default animation");
         }
+        animator
     }
-    pub fn set_animation(&mut self, animation: String) {
-        self.animation = animation.to_string();
-        match animation.to_string().as_str() {
-            "fade" => self.animator = AnimatorEnum::Fades(Fades::new(&self.config)),
-            "ripple" => self.animator = AnimatorEnum::Ripples(Ripples::new(&self.config)),
-            _ => self.animator = AnimatorEnum::Default(DefaultAnimator::new(&self.config)),
+    /// Takes the settings of a reloaded config. Only what changed in it is
+    /// applied, what was changed at runtime is kept otherwise.
+    pub fn set_config(&mut self, config: &Config) {
+        let (old, new) = (&self.config.leds, &config.leds);
+        if old.transition != new.transition {
+            self.transition = new.transition.kind;
+            self.transition_duration = Duration::from_millis(new.transition.duration);
         }
-    }
-    pub fn update(&mut self) {
-        match &mut self.animator {
-            AnimatorEnum::Fades(fades) => fades.update(),
-            AnimatorEnum::Ripples(ripples) => ripples.update(),
-            AnimatorEnum::Default(_) => {}
-            AnimatorEnum::Static(_) => {}
+        if old.idle != new.idle {
+            self.idle.timeout = Duration::from_secs(new.idle.timeout);
+            self.idle.animation = new.idle.animation.to_string();
         }
-    }
-    pub fn draw(&mut self, controller: &mut Controller) {
-        match &mut self.animator {
-            AnimatorEnum::Fades(fades) => fades.draw(controller),
-            AnimatorEnum::Ripples(ripples) => ripples.draw(controller),
-            AnimatorEnum::Default(default) => default.draw(controller),
-            AnimatorEnum::Static(static_color) => static_color.draw(controller),
+        for layer in self.layers.iter_mut() {
+            if old.animations.get(&layer.animation) != new.animations.get(&layer.animation) {
+                let schema: Vec<ParameterSchema> = layer
+                    .animator
+                    .parameters()
+                    .0
+                    .iter()
+                    .map(|parameter| parameter.schema)
+                    .collect();
+                *layer.animator.parameters_mut() =
+                    Parameters::new(&schema, config, &layer.animation);
+            }
         }
+        self.config = config.clone();
     }
-    pub fn note_on(&mut self, led_index: usize, color: [u8; 4]) {
-        match &mut self.animator {
-            AnimatorEnum::Fades(fades) => fades.add_fade(led_index, color),
-            AnimatorEnum::Ripples(ripples) => ripples.add_ripple(led_index, color),
-            AnimatorEnum::Default(default) => default.note_on(led_index, color),
-            AnimatorEnum::Static(_) => {}
-        }
+    /// Name of the primary animation
+    pub fn animation(&self) -> &str {
+        self.primary().map_or("", |layer| layer.animation.as_str())
     }
-    pub fn note_off(&mut self, led_index: usize, _color: [u8; 4]) {
-        match &mut self.animator {
-            AnimatorEnum::Fades(fades) => fades.start_fade(led_index),
-            AnimatorEnum::Ripples(_) => {}
-            AnimatorEnum::Default(default) => default.note_off(led_index),
-            AnimatorEnum::Static(_) => {}
-        }
+    /// Parameters an animation starts with, its defaults overridden by the
+    /// config
+    pub fn initial_parameters(&self, animation: &str) -> Parameters {
+        let schema = get_parameter_schema(animation).unwrap_or(&[]);
+        Parameters::new(schema, &self.config, animation)
     }
-}
-
-#[derive(Debug)]
-pub struct Fade {
-    pub position: usize,
-    pub color: [u8; 4],
-    pub fade: i8,
-    pub started: bool,
-}
-#[derive(Debug)]
-pub struct Fades {
-    pub fades: Vec<Fade>,
-    pub config: Config,
-}
-impl Fades {
-    pub fn new(config: &Config) -> Fades {
-        Fades {
-            fades: Vec::new(),
-            config: config.clone(),
-        }
+    pub fn primary(&self) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.id == 0)
     }
-    pub fn add_fade(&mut self, position: usize, color: [u8; 4]) {
-        self.fades.push(Fade {
-            position,
-            color,
-            fade: self.config.leds.fade,
-            started: false,
-        });
+    pub fn layer_mut(&mut self, id: usize) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.id == id)
     }
-    pub fn start_fade(&mut self, position: usize) {
-        for fade in self.fades.iter_mut() {
-            if fade.position == position {
-                fade.started = true;
-            }
-        }
+    /// Switches the primary layer to the animation with the given name,
+    /// leaving it untouched if there is no such animation. This ends the
+    /// ambient animation if idle.
+    pub fn set_animation(&mut self, animation: String) -> Result<(), String> {
+        self.set_layer_animation(0, animation)?;
+        self.idle.wake();
+        Ok(())
+    }
+    /// Switches a layer to another animation, transitioning from the
+    /// outgoing one which keeps running until the transition is over.
+    pub fn set_layer_animation(&mut self, id: usize, animation: String) -> Result<(), String> {
+        let animator = self.create_animation(&animation)?;
+        let (kind, duration) = (self.transition, self.transition_duration);
+        let layer = self
+            .layer_mut(id)
+            .ok_or_else(|| format!("Unknown layer {}", id))?;
+        let mut outgoing = std::mem::replace(&mut layer.animator, animator);
+        outgoing.stop();
+        let blank = vec![[0, 0, 0, 0]; layer.frame.len()];
+        let from = std::mem::replace(&mut layer.frame, blank);
+        layer.transition = Some(Transition::new(from, Some(outgoing), kind, duration));
+        layer.animation = animation;
+        Ok(())
     }
-    pub fn update(&mut self) {
-        for fade in self.fades.iter_mut() {
-            fade.color = [
-                (fade.color[0] as f32 * (fade.fade as f32 / self.config.leds.fade as f32)) as u8,
-                (fade.color[1] as f32 * (fade.fade as f32 / self.config.leds.fade as f32)) as u8,
-                (fade.color[2] as f32 * (fade.fade as f32 / self.config.leds.fade as f32)) as u8,
-                fade.color[3],
-            ];
-            if fade.started {
-                fade.fade -= 1;
+    /// Forwards a new color mode to every layer, transitioning from what
+    /// they showed before. The first one, at startup, and the current one
+    /// are applied without a transition.
+    pub fn set_color_mode(&mut self, color_mode: &ColorMode) {
+        let transition = match &self.color_mode {
+            Some(current) if current.mode == color_mode.mode => return,
+            Some(_) => true,
+            None => false,
+        };
+        for layer in self.layers.iter_mut() {
+            if transition {
+                layer.transition = Some(Transition::new(
+                    layer.output(),
+                    None,
+                    self.transition,
+                    self.transition_duration,
+                ));
             }
+            layer.animator.set_color_mode(color_mode);
         }
-        self.fades.retain(|fade| fade.fade > 0);
+        self.color_mode = Some(color_mode.clone());
     }
-    pub fn draw(&self, controller: &mut Controller) {
-        let leds = controller.leds_mut(self.config.leds.channel);
-        for fade in self.fades.iter() {
-            let led = leds.get_mut(fade.position).expect("Led not found");
-            *led = fade.color;
+    fn create_animation(&self, animation: &str) -> Result<Box<dyn Animation>, String> {
+        let mut animator = create_animation(animation, &self.config)
+            .ok_or_else(|| format!("Unknown animation `{}`", animation))?;
+        if let Some(color_mode) = &self.color_mode {
+            animator.set_color_mode(color_mode);
         }
+        animator.start();
+        Ok(animator)
     }
-}
-
-pub struct Led {
-    pub position: usize,
-    pub color: [u8; 4],
-}
-pub struct DefaultAnimator {
-    pub config: Config,
-    pub leds: Vec<Led>,
-}
-impl DefaultAnimator {
-    pub fn new(config: &Config) -> Self {
-        Self {
-            config: config.clone(),
-            leds: Vec::new(),
-        }
+    /// Adds a layer on top of the others, returning its id
+    pub fn add_layer(
+        &mut self,
+        animation: String,
+        opacity: f32,
+        blend: BlendMode,
+    ) -> Result<usize, String> {
+        let animator = self.create_animation(&animation)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.layers.push(Layer {
+            id,
+            animation,
+            animator,
+            opacity: opacity.clamp(0.0, 1.0),
+            blend,
+            frame: vec![[0, 0, 0, 0]; self.config.leds.num_leds],
+            transition: None,
+        });
+        Ok(id)
     }
-    pub fn note_on(&mut self, position: usize, color: [u8; 4]) {
-        self.leds.push(Led { position, color });
+    /// Removes a layer, the primary one can't be removed
+    pub fn remove_layer(&mut self, id: usize) -> Result<(), String> {
+        if id == 0 {
+            return Err("The primary layer can't be removed".to_string());
+        }
+        let index = self
+            .layers
+            .iter()
+            .position(|layer| layer.id == id)
+            .ok_or_else(|| format!("Unknown layer {}", id))?;
+        self.layers.remove(index).animator.stop();
+        Ok(())
     }
-    pub fn note_off(&mut self, position: usize) {
-        self.leds
-            .iter_mut()
-            .find(|led| led.position == position)
-            .map(|led| led.color = [0, 0, 0, 0]);
+    /// Moves a layer to `index` in the stack, 0 being the bottom
+    pub fn move_layer(&mut self, id: usize, index: usize) -> Result<(), String> {
+        let current = self
+            .layers
+            .iter()
+            .position(|layer| layer.id == id)
+            .ok_or_else(|| format!("Unknown layer {}", id))?;
+        let layer = self.layers.remove(current);
+        self.layers.insert(index.min(self.layers.len()), layer);
+        Ok(())
     }
-    pub fn draw(&self, controller: &mut Controller) {
-        let leds = controller.leds_mut(self.config.leds.channel);
-        for self_led in self.leds.iter() {
-            let led = leds.get_mut(self_led.position).expect("Led not found");
-            *led = self_led.color;
+    /// Advances every layer by `delta` seconds
+    pub fn update(&mut self, delta: f32) {
+        if self.idle.timed_out() {
+            let performance = self.animation().to_string();
+            let ambient = self.idle.animation.to_string();
+            match self.set_layer_animation(0, ambient) {
+                Ok(()) => self.idle.performance = Some(performance),
+                Err(e) => {
+                    warn!(
+                        "<yellow>[WS2812]</> Could not switch to the idle animation: {}",
+                        e
+                    );
+                    self.idle.wake();
+                }
+            }
         }
-    }
-}
-pub struct StaticColor {
-    pub config: Config,
-}
-impl StaticColor {
-    pub fn new(config: &Config) -> Self {
-        Self {
-            config: config.clone(),
+        for layer in self.layers.iter_mut() {
+            layer.animator.update(delta);
+            if let Some(transition) = layer.transition.as_mut() {
+                transition.update(delta);
+            }
+            if layer
+                .transition
+                .as_ref()
+                .is_some_and(|transition| transition.is_finished())
+            {
+                layer.transition = None;
+            }
         }
     }
-    pub fn draw(&self, controller: &mut Controller) {
-        let leds = controller.leds_mut(self.config.leds.channel);
-        for led in leds.iter_mut() {
-            let rgb = hex_to_rgb(&self.config.leds.color_mode);
-            *led = [rgb[2], rgb[1], rgb[0], 0];
-        }
+    /// Stored stats asked for by the layers since the previous frame
+    pub fn history_requests(&mut self) -> Vec<HistoryRequest> {
+        self.layers
+            .iter_mut()
+            .filter_map(|layer| {
+                let window = layer.animator.history_request()?;
+                Some((layer.id, window))
+            })
+            .collect()
     }
-}
-#[derive(Debug, Clone)]
-pub struct TrailPart {
-    pub position: usize,
-    pub last_position: usize,
-    pub color: [u8; 4],
-}
-#[derive(Debug)]
-pub struct Ripple {
-    left_trail: Vec<TrailPart>,
-    right_trail: Vec<TrailPart>,
-}
-#[derive(Debug)]
-pub struct Ripples {
-    pub config: Config,
-    pub ripples: Vec<Ripple>,
-}
-
-impl Ripples {
-    pub fn new(config: &Config) -> Ripples {
-        Ripples {
-            config: config.clone(),
-            ripples: Vec::new(),
+    /// Answers the request of a layer for stored stats, if it's still there
+    pub fn set_history(&mut self, id: usize, window: u64, key_hits: &[u32], key_map: &KeyMap) {
+        if let Some(layer) = self.layer_mut(id) {
+            layer.animator.set_history(window, key_hits, key_map);
         }
     }
-    pub fn add_ripple(&mut self, position: usize, color: [u8; 4]) {
-        // push a new ripple and fill left_trail and right_trail with the first trail parts
-        self.ripples.push(Ripple {
-            left_trail: vec![TrailPart {
-                position,
-                color,
-                last_position: position,
-            }],
-            right_trail: vec![TrailPart {
-                position,
-                color,
-                last_position: position,
-            }],
-        });
+    pub fn draw(&mut self, leds: &mut [RawColor]) {
+        for layer in self.layers.iter_mut() {
+            layer.animator.draw(&mut layer.frame);
+        }
+        composite(&self.layers, leds);
     }
-    pub fn update(&mut self) {
-        for ripple in self.ripples.iter_mut() {
-            for trail_part in ripple.left_trail.iter_mut() {
-                if (trail_part.position as i32 - 1) >= 0 {
-                    trail_part.last_position = trail_part.position;
-                    trail_part.position -= 1;
-                } else {
-                    trail_part.color = [0, 0, 0, 0];
-                }
-            }
-            for trail_part in ripple.right_trail.iter_mut() {
-                if (trail_part.position as i32 + 1) < self.config.leds.num_leds as i32 {
-                    trail_part.last_position = trail_part.position;
-                    trail_part.position += 1;
-                } else {
-                    trail_part.color = [0, 0, 0, 0];
-                }
+    /// Wakes the primary layer up from the ambient animation first
+    pub fn note_on(&mut self, key: &Key, color: [u8; 4], velocity: u8) {
+        if let Some(performance) = self.idle.wake() {
+            if let Err(e) = self.set_layer_animation(0, performance) {
+                warn!(
+                    "<yellow>[WS2812]</> Could not leave the idle animation: {}",
+                    e
+                );
             }
         }
+        for layer in self.layers.iter_mut() {
+            layer.animator.note_on(key, color, velocity);
+        }
     }
-    pub fn draw(&mut self, controller: &mut Controller) {
-        let leds = controller.leds_mut(self.config.leds.channel);
-        for ripple in self.ripples.iter_mut() {
-            for trail_part in ripple.left_trail.iter_mut() {
-                if let Some(led) = leds.get_mut(trail_part.position) {
-                    *led = trail_part.color;
-                }
-                if let Some(led) = leds.get_mut(trail_part.last_position) {
-                    *led = [0, 0, 0, 0];
-                }
-            }
-            for trail_part in ripple.right_trail.iter_mut() {
-                if let Some(led) = leds.get_mut(trail_part.position) {
-                    *led = trail_part.color;
-                }
-                if let Some(led) = leds.get_mut(trail_part.last_position) {
-                    *led = [0, 0, 0, 0];
-                }
+    /// Also reaches the outgoing animations, so that their notes don't stay lit
+    pub fn note_off(&mut self, key: &Key, color: [u8; 4]) {
+        for layer in self.layers.iter_mut() {
+            layer.animator.note_off(key, color);
+            if let Some(outgoing) = layer
+                .transition
+                .as_mut()
+                .and_then(|transition| transition.outgoing.as_mut())
+            {
+                outgoing.note_off(key, color);
             }
         }
     }
 }
 
+/// Color modes other than a solid color
+pub const COLOR_MODES: [&str; 3] = ["rainbow", "random", "harmony"];
+
 #[derive(Debug, Clone)]
 pub struct ColorMode {
     pub mode: String,
     pub num_leds: usize,
+    /// Updated by the MIDI thread through the LED commands
+    pub harmony: HarmonySnapshot,
 }
 impl ColorMode {
     pub fn new(mode: &String, num_leds: &usize) -> ColorMode {
         ColorMode {
             mode: mode.to_string(),
             num_leds: *num_leds,
+            harmony: HarmonySnapshot::default(),
         }
     }
     pub fn set_color_mode(&mut self, mode: String) {
         self.mode = mode;
     }
-    pub fn get_color(&self, position: usize) -> [u8; 4] {
+    /// Accepts the named modes and `#rrggbb` colors
+    pub fn validate(mode: &str) -> Result<(), String> {
+        let is_color = mode.len() == 7
+            && mode.starts_with('#')
+            && mode[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if COLOR_MODES.contains(&mode) || is_color {
+            Ok(())
+        } else {
+            Err(format!(
+                "Unknown color mode `{}`, expected one of {} or a `#rrggbb` color",
+                mode,
+                COLOR_MODES.join(", ")
+            ))
+        }
+    }
+    pub fn get_color(&self, position: usize, note: u8) -> [u8; 4] {
         match self.mode.as_str() {
             "rainbow" => self.get_rainbow_color(position),
             "random" => self.get_random_color(),
+            "harmony" => self.harmony.get_color(note),
             _ => self.get_solid_color(&self.mode),
         }
     }
+    /// Color of an LED in a single rainbow along the strip, with the hue
+    /// `rainbow_fill_single_cycle` gives it
     pub fn get_rainbow_color(&self, position: usize) -> [u8; 4] {
-        let mut colors = vec![ColorRGB::Black; self.num_leds];
-        colors.rainbow_fill_single_cycle(0);
-        [
-            colors[position][0],
-            colors[position][1],
-            colors[position][2],
-            0,
-        ]
+        let hue_delta = (255u32 << 24) / self.num_leds.max(1) as u32;
+        let hue = (hue_delta.wrapping_mul(position as u32) >> 24) as u8;
+        let color = ColorRGB::from(HSV::new(hue, 255, 255));
+        [color.r, color.g, color.b, 0]
     }
     pub fn get_random_color(&self) -> [u8; 4] {
         let color = [
@@ -13,30 +13,62 @@ import { useEffect, useState } from 'react';
 
 import http from './http';
 
+type AnimationParam = {
+  name: string;
+  description: string;
+  kind: 'integer' | 'float';
+  min: number;
+  max: number;
+  default: number;
+  value: number;
+};
+
 const App = () => {
   const [colorMode, setColorMode] = useState('');
   const [solidColor, setSolidColor] = useState('#000000');
   const [animation, setAnimation] = useState('');
+  const [animations, setAnimations] = useState<
+    { label: string; value: string }[]
+  >([]);
   const [brightness, setBrightness] = useState(0);
+  const [params, setParams] = useState<AnimationParam[]>([]);
+  const fetchParams = () => {
+    http.get('/animation/params').then((response) => {
+      setParams(response.data.data);
+    });
+  };
   useEffect(() => {
     http.get('/color_mode').then((response) => {
-      switch (response.data.data) {
+      const { mode } = response.data.data;
+      switch (mode) {
         case 'rainbow':
         case 'random':
-          setColorMode(response.data.data);
+        case 'harmony':
+          setColorMode(mode);
           break;
         default:
           setColorMode('solid');
-          setSolidColor(response.data.data);
+          setSolidColor(mode);
           break;
       }
     });
     http.get('/animation').then((response) => {
-      setAnimation(response.data.data);
+      setAnimation(response.data.data.animation);
+    });
+    http.get('/animations').then((response) => {
+      setAnimations(
+        response.data.data.map(
+          (animation: { name: string; description: string }) => ({
+            label: animation.name,
+            value: animation.name,
+          })
+        )
+      );
     });
     http.get('/brightness').then((response) => {
-      setBrightness(response.data.data);
+      setBrightness(Math.round((response.data.data.brightness / 255) * 100));
     });
+    fetchParams();
   }, []);
   return (
     <MantineProvider withNormalizeCSS withGlobalStyles>
@@ -48,13 +80,14 @@ const App = () => {
             data={[
               { label: 'Rainbow', value: 'rainbow' },
               { label: 'Random', value: 'random' },
+              { label: 'Harmony', value: 'harmony' },
               { label: 'Solid Color', value: 'solid' },
             ]}
             value={colorMode}
             onChange={(value) => {
               setColorMode(value as string);
-              if (['rainbow', 'random'].includes(value as string)) {
-                http.post('/color_mode', value);
+              if (['rainbow', 'random', 'harmony'].includes(value as string)) {
+                http.post('/color_mode', { mode: value });
               }
             }}
           />
@@ -82,7 +115,7 @@ const App = () => {
                 setSolidColor(value);
               }}
               onBlur={() => {
-                http.post('/color_mode', solidColor);
+                http.post('/color_mode', { mode: solidColor });
               }}
             />
           )}
@@ -90,18 +123,43 @@ const App = () => {
         <Box mt='lg'>
           <Select
             label='Animation'
-            data={[
-              { label: 'Fade', value: 'fade' },
-              { label: 'Ripple', value: 'ripple' },
-              { label: 'None', value: 'none' },
-              { label: 'Static Color', value: 'static' },
-            ]}
+            data={animations}
             value={animation}
             onChange={(value) => {
               setAnimation(value as string);
-              http.post('/animation', value);
+              http
+                .post('/animation', { animation: value })
+                .then(fetchParams);
             }}
           />
+          {params.map((param) => (
+            <Box key={param.name} mt='md'>
+              <Text size='sm' weight={450} mb='xs' title={param.description}>
+                {param.name}
+              </Text>
+              <Slider
+                min={param.min}
+                max={param.max}
+                step={
+                  param.kind === 'integer' ? 1 : (param.max - param.min) / 100
+                }
+                precision={param.kind === 'integer' ? 0 : 4}
+                value={param.value}
+                onChange={(value) => {
+                  setParams(
+                    params.map((p) =>
+                      p.name === param.name ? { ...p, value } : p
+                    )
+                  );
+                }}
+                onChangeEnd={(value) => {
+                  http
+                    .patch('/animation/params', { [param.name]: value })
+                    .then(fetchParams);
+                }}
+              />
+            </Box>
+          ))}
         </Box>
         <Box mt='lg'>
           <Text size='sm' weight={450} mb='xs'>
@@ -111,7 +169,9 @@ const App = () => {
             size='lg'
             value={brightness}
             onChangeEnd={(e) => {
-              http.post('/brightness', (e / 100) * 255);
+              http.post('/brightness', {
+                brightness: Math.round((e / 100) * 255),
+              });
             }}
             onChange={(value) => {
               setBrightness(value);
@@ -120,11 +180,11 @@ const App = () => {
         </Box>
         <Button
           onClick={() => {
-            http.post('/animation', animation);
+            http.post('/animation', { animation });
             if (colorMode === 'solid') {
-              http.post('/color_mode', solidColor);
+              http.post('/color_mode', { mode: solidColor });
             } else {
-              http.post('/color_mode', colorMode);
+              http.post('/color_mode', { mode: colorMode });
             }
           }}
         >
@@ -7,6 +7,7 @@ const methods = {
   get: client.get,
   post: client.post,
   put: client.put,
+  patch: client.patch,
   delete: client.delete,
 };
 